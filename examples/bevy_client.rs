@@ -3,9 +3,11 @@ use ::bevy::prelude::*;
 use bevy_websocket_adapter::{
     bevy::{WebSocketClient, WsMessageInserter},
     impl_message_type,
-    client::Client,
+    shared::NetworkEvent,
+    client::{Client, ClientConfig, ReconnectPolicy},
 };
 
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -14,24 +16,32 @@ struct DummyEvent {
 }
 impl_message_type!(DummyEvent, "dummy");
 
-fn connect_to_server(mut ws: ResMut<Client>) {
-    ws.connect("ws://127.0.0.1:12345".to_string());
+fn listen_for_events(mut evs: EventReader<NetworkEvent>) {
+    for ev in evs.iter() {
+        info!("received NetworkEvent : {:?}", ev);
+    }
 }
 
 fn send_dummies(
     client: Res<Client>
 
 ) {
-    client.send_message(&DummyEvent{a: 2});
+    if let Err(e) = client.send_message(&DummyEvent{a: 2}) {
+        warn!("failed to send dummy message: {}", e);
+    }
 }
 
 fn main() {
     simple_logger::init_with_level(log::Level::Debug).unwrap();
     App::build()
         .add_plugins(MinimalPlugins)
-        .add_plugin(WebSocketClient::default())
-        .add_startup_system(connect_to_server.system())
+        .add_plugin(WebSocketClient::with_config(
+            ClientConfig::default()
+                .endpoint("ws://127.0.0.1:12345")
+                .reconnect(ReconnectPolicy::default()),
+        ))
         .add_message_type::<DummyEvent>()
         .add_system(send_dummies.system())
+        .add_system(listen_for_events.system())
         .run();
 }
@@ -3,15 +3,10 @@ use ::bevy::prelude::*;
 use bevy_websocket_adapter::{
     bevy::WebSocketServer,
     shared::NetworkEvent,
-    server::Server,
+    server::ServerConfig,
 };
 use log::info;
 
-fn start_listen(mut ws: ResMut<Server>) {
-    ws.listen("0.0.0.0:12345")
-        .expect("failed to start websocket server");
-}
-
 fn listen_for_events(mut evs: EventReader<NetworkEvent>) {
     for ev in evs.iter() {
         info!("received NetworkEvent : {:?}", ev);
@@ -22,8 +17,9 @@ fn main() {
     simple_logger::init_with_level(log::Level::Debug).unwrap();
     App::build()
         .add_plugins(MinimalPlugins)
-        .add_plugin(WebSocketServer::default())
-        .add_startup_system(start_listen.system())
+        .add_plugin(WebSocketServer::with_config(
+            ServerConfig::default().bind("0.0.0.0:12345"),
+        ))
         .add_system(listen_for_events.system())
         .run();
 }
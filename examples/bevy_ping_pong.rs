@@ -1,10 +1,10 @@
 extern crate bevy_websocket_adapter;
 use ::bevy::prelude::*;
 use bevy_websocket_adapter::{
-    bevy::{WebSocketServer, WsMessageInserter},
+    bevy::{Outgoing, WebSocketServer, WsMessageInserter},
     impl_message_type,
     shared::ConnectionHandle,
-    server::Server,
+    server::ServerConfig,
 };
 use log::info;
 
@@ -18,15 +18,13 @@ impl_message_type!(Ping, "ping");
 struct Pong {}
 impl_message_type!(Pong, "pong");
 
-fn start_listen(mut ws: ResMut<Server>) {
-    ws.listen("0.0.0.0:12345")
-        .expect("failed to start websocket server");
-}
-
-fn respond_to_pings(mut evs: EventReader<(ConnectionHandle, Ping)>, srv: Res<Server>) {
+fn respond_to_pings(
+    mut evs: EventReader<(ConnectionHandle, Ping)>,
+    mut pongs: EventWriter<Outgoing<Pong>>,
+) {
     for (handle, ev) in evs.iter() {
         info!("received ping from {:?} : {:?}", handle, ev);
-        srv.send_message(handle, &Pong {})
+        pongs.send(Outgoing::to(handle.clone(), Pong {}));
     }
 }
 
@@ -34,9 +32,11 @@ fn main() {
     simple_logger::init_with_level(log::Level::Debug).unwrap();
     App::build()
         .add_plugins(MinimalPlugins)
-        .add_plugin(WebSocketServer::default())
-        .add_startup_system(start_listen.system())
+        .add_plugin(WebSocketServer::with_config(
+            ServerConfig::default().bind("0.0.0.0:12345"),
+        ))
         .add_message_type::<Ping>()
+        .add_outgoing_type::<Pong>()
         .add_system(respond_to_pings.system())
         .run();
 }
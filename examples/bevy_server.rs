@@ -4,7 +4,7 @@ use bevy_websocket_adapter::{
     bevy::{WebSocketServer, WsMessageInserter},
     impl_message_type,
     shared::ConnectionHandle,
-    server::Server,
+    server::ServerConfig,
 };
 use log::info;
 
@@ -16,11 +16,6 @@ struct DummyEvent {
 }
 impl_message_type!(DummyEvent, "dummy");
 
-fn start_listen(mut ws: ResMut<Server>) {
-    ws.listen("0.0.0.0:12345")
-        .expect("failed to start websocket server");
-}
-
 fn listen_for_dummy(mut evs: EventReader<(ConnectionHandle, DummyEvent)>) {
     for (handle, ev) in evs.iter() {
         info!("received DummyEvent from {:?} : {:?}", handle, ev);
@@ -31,8 +26,9 @@ fn main() {
     simple_logger::init_with_level(log::Level::Debug).unwrap();
     App::build()
         .add_plugins(MinimalPlugins)
-        .add_plugin(WebSocketServer::default())
-        .add_startup_system(start_listen.system())
+        .add_plugin(WebSocketServer::with_config(
+            ServerConfig::default().bind("0.0.0.0:12345"),
+        ))
         .add_message_type::<DummyEvent>()
         .add_system(listen_for_dummy.system())
         .run();
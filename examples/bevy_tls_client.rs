@@ -0,0 +1,38 @@
+extern crate bevy_websocket_adapter;
+use ::bevy::prelude::*;
+use bevy_websocket_adapter::{
+    bevy::{WebSocketClient, WsMessageInserter},
+    client::{Client, ClientConfig, ClientTlsConfig},
+    impl_message_type,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct DummyEvent {
+    a: u32,
+}
+impl_message_type!(DummyEvent, "dummy");
+
+fn send_dummies(client: Res<Client>) {
+    if let Err(e) = client.send_message(&DummyEvent { a: 2 }) {
+        warn!("failed to send dummy message: {}", e);
+    }
+}
+
+fn main() {
+    simple_logger::init_with_level(log::Level::Debug).unwrap();
+    let ca = std::env::args().nth(1).unwrap_or_else(|| "cert.pem".to_string());
+    let mut tls = ClientTlsConfig::new();
+    tls.add_root_certificate_pem_file(ca)
+        .expect("failed to load root certificate");
+    App::build()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(WebSocketClient::with_config(
+            ClientConfig::default().endpoint("wss://localhost:12345").tls(tls),
+        ))
+        .add_message_type::<DummyEvent>()
+        .add_system(send_dummies.system())
+        .run();
+}
@@ -0,0 +1,29 @@
+extern crate bevy_websocket_adapter;
+use ::bevy::prelude::*;
+use bevy_websocket_adapter::{
+    bevy::WebSocketServer,
+    server::{ServerConfig, ServerTlsConfig},
+    shared::NetworkEvent,
+};
+use log::info;
+
+fn listen_for_events(mut evs: EventReader<NetworkEvent>) {
+    for ev in evs.iter() {
+        info!("received NetworkEvent : {:?}", ev);
+    }
+}
+
+fn main() {
+    simple_logger::init_with_level(log::Level::Debug).unwrap();
+    let mut args = std::env::args().skip(1);
+    let cert = args.next().unwrap_or_else(|| "cert.pem".to_string());
+    let key = args.next().unwrap_or_else(|| "key.pem".to_string());
+    let tls = ServerTlsConfig::from_pem_files(cert, key).expect("failed to load TLS certificate");
+    App::build()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(WebSocketServer::with_config(
+            ServerConfig::default().bind("0.0.0.0:12345").tls(tls),
+        ))
+        .add_system(listen_for_events.system())
+        .run();
+}
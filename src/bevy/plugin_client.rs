@@ -1,27 +1,47 @@
-use crate::shared::{ConnectionHandle, Enveloppe, GenericParser, NetworkEvent};
-use crate::client::Client;
+use crate::shared::{
+    ConnectionHandle, Enveloppe, GenericParser, MessageType, NetworkError, NetworkEvent,
+};
+use crate::client::{Client, ClientConfig, PendingResponse};
+use super::RoundTripTimes;
 use bevy::prelude::*;
 use log::{trace, warn};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 #[derive(Default, Debug)]
-pub struct WebSocketClient {}
+pub struct WebSocketClient {
+    config: ClientConfig,
+}
+
+impl WebSocketClient {
+    /// Create the `Client` resource from `config`, and connect to its endpoint at startup if it
+    /// has one. An invalid endpoint is reported as a `NetworkEvent::Error`.
+    pub fn with_config(config: ClientConfig) -> Self {
+        Self { config }
+    }
+}
 
 impl Plugin for WebSocketClient {
     fn build(&self, app: &mut AppBuilder) {
-        let client = Client::new();
-        let router = Arc::new(Mutex::new(GenericParser::new()));
-        let map = HashMap::<String, Vec<(ConnectionHandle, Enveloppe)>>::new();
-        let network_events = Vec::<NetworkEvent>::new();
-        app.insert_resource(client)
-            .insert_resource(router)
-            .insert_resource(map)
-            .insert_resource(network_events)
-            .add_event::<NetworkEvent>()
-            .add_stage_before(CoreStage::First, "network", SystemStage::single_threaded())
+        let rt = super::shared::shared_runtime(app, self.config.worker_threads);
+        let client = Client::with_runtime(self.config.clone(), rt);
+        super::shared::network_stage(app)
+            .insert_resource(client)
             .add_system_to_stage("network", consume_messages.system())
-            .add_system_to_stage("network", super::shared::handle_network_events.system());
+            .add_system_to_stage("network", update_round_trip_times.system());
+        if self.config.endpoint.is_some() {
+            app.add_startup_system(start_client.system());
+        }
+    }
+}
+
+fn start_client(mut client: ResMut<Client>, mut network_events: ResMut<Vec<NetworkEvent>>) {
+    if let Err(e) = client.start() {
+        warn!("failed to start websocket client: {}", e);
+        let handle = ConnectionHandle {
+            uuid: uuid::Uuid::nil(),
+        };
+        network_events.push(NetworkEvent::Error(Some(handle), e.into()));
     }
 }
 
@@ -29,29 +49,31 @@ fn consume_messages(
     client: Res<Client>,
     mut hmap: ResMut<HashMap<String, Vec<(ConnectionHandle, Enveloppe)>>>,
     mut network_events: ResMut<Vec<NetworkEvent>>,
+    router: Res<Arc<Mutex<GenericParser>>>,
 ) {
     if !client.is_running() {
         return;
     }
 
+    let codec = client.codec();
     while let Some(ev) = client.try_recv() {
         match ev {
             NetworkEvent::Message(handle, raw_ev) => {
                 trace!("consuming message from {:?}", handle);
-                if let Ok(enveloppe) = serde_json::from_reader::<std::io::Cursor<Vec<u8>>, Enveloppe>(
-                    std::io::Cursor::new(raw_ev),
-                ) {
-                    let tp = enveloppe.message_type.to_string();
-                    let mut v = if let Some(x) = hmap.remove(&tp) {
-                        x
-                    } else {
-                        Vec::new()
-                    };
-                    v.push((handle, enveloppe.clone()));
-                    hmap.insert(tp, v);
-                } else {
-                    warn!("failed to deserialize message from {:?}", handle);
-                    continue;
+                match Enveloppe::decode(codec.clone(), raw_ev) {
+                    Ok(enveloppe) if router.lock().unwrap().has_type(&enveloppe.message_type) => {
+                        let tp = enveloppe.message_type.to_string();
+                        let mut v = hmap.remove(&tp).unwrap_or_default();
+                        v.push((handle, enveloppe));
+                        hmap.insert(tp, v);
+                    }
+                    Ok(enveloppe) => {
+                        warn!(
+                            "received unregistered type '{}' from server",
+                            enveloppe.message_type
+                        );
+                    }
+                    Err(_) => warn!("failed to deserialize message from {:?}", handle),
                 }
             }
             other => {
@@ -62,3 +84,60 @@ fn consume_messages(
     }
 }
 
+fn update_round_trip_times(client: Res<Client>, mut rtts: ResMut<RoundTripTimes>) {
+    let handle = ConnectionHandle {
+        uuid: uuid::Uuid::nil(),
+    };
+    match client.rtt() {
+        Some(rtt) => rtts.0.insert(handle, rtt),
+        None => rtts.0.remove(&handle),
+    };
+}
+
+/// Requests awaiting their response, registered with `add_response_type::<T>()`. Each response
+/// is emitted as an `IncomingResponse<T>` event once it arrives.
+pub struct PendingResponses<T>(Vec<PendingResponse<T>>);
+
+impl<T> Default for PendingResponses<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T> PendingResponses<T> {
+    pub fn push(&mut self, response: PendingResponse<T>) {
+        self.0.push(response);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Outcome of a request sent with `Client::request`.
+#[derive(Debug)]
+pub struct IncomingResponse<T> {
+    /// Correlation id of the request, see `PendingResponse::id`.
+    pub id: u64,
+    pub result: Result<T, NetworkError>,
+}
+
+pub(crate) fn poll_responses<T: MessageType>(
+    mut pending: ResMut<PendingResponses<T>>,
+    mut responses: EventWriter<IncomingResponse<T>>,
+) {
+    pending.0.retain_mut(|response| match response.try_recv() {
+        Some(result) => {
+            responses.send(IncomingResponse {
+                id: response.id(),
+                result,
+            });
+            false
+        }
+        None => true,
+    });
+}
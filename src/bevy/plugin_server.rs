@@ -1,27 +1,49 @@
-use crate::shared::{ConnectionHandle, Enveloppe, GenericParser, NetworkEvent};
-use crate::server::Server;
+use crate::shared::{
+    ConnectionHandle, Enveloppe, ErrorMessage, GenericParser, MessageType, NetworkEvent,
+};
+use crate::server::{Server, ServerConfig};
+use super::{shared::ErrorReports, RoundTripTimes};
 use bevy::prelude::*;
-use log::{trace, warn};
+use log::{debug, trace, warn};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 #[derive(Default, Debug)]
-pub struct WebSocketServer {}
+pub struct WebSocketServer {
+    config: ServerConfig,
+}
+
+impl WebSocketServer {
+    /// Create the `Server` resource from `config`, and listen on its bind address at startup
+    /// if it has one. Failing to listen is reported as a `NetworkEvent::Error`.
+    pub fn with_config(config: ServerConfig) -> Self {
+        Self { config }
+    }
+}
 
 impl Plugin for WebSocketServer {
     fn build(&self, app: &mut AppBuilder) {
-        let server = Server::new();
-        let router = Arc::new(Mutex::new(GenericParser::new()));
-        let map = HashMap::<String, Vec<(ConnectionHandle, Enveloppe)>>::new();
-        let network_events = Vec::<NetworkEvent>::new();
-        app.insert_resource(server)
-            .insert_resource(router)
-            .insert_resource(map)
-            .insert_resource(network_events)
-            .add_event::<NetworkEvent>()
-            .add_stage_before(CoreStage::First, "network", SystemStage::single_threaded())
+        let rt = super::shared::shared_runtime(app, self.config.worker_threads);
+        let server = Server::with_runtime(self.config.clone(), rt);
+        super::shared::network_stage(app)
+            .insert_resource(server)
             .add_system_to_stage("network", consume_messages.system())
-            .add_system_to_stage("network", super::shared::handle_network_events.system());
+            .add_system_to_stage("network", update_round_trip_times.system());
+        if self.config.bind.is_some() {
+            app.add_startup_system(start_server.system());
+        }
+        if self.config.report_errors {
+            app.insert_resource(ErrorReports::default())
+                .add_system_to_stage("network_flush", send_error_reports.system());
+        }
+    }
+}
+
+fn start_server(mut server: ResMut<Server>, mut network_events: ResMut<Vec<NetworkEvent>>) {
+    if let Err(e) = server.start() {
+        warn!("failed to start websocket server: {}", e);
+        network_events.push(NetworkEvent::Error(None, e.into()));
     }
 }
 
@@ -29,6 +51,8 @@ fn consume_messages(
     server: Res<Server>,
     mut hmap: ResMut<HashMap<String, Vec<(ConnectionHandle, Enveloppe)>>>,
     mut network_events: ResMut<Vec<NetworkEvent>>,
+    router: Res<Arc<Mutex<GenericParser>>>,
+    mut errors: Option<ResMut<ErrorReports>>,
 ) {
     if !server.is_running() {
         return;
@@ -38,20 +62,33 @@ fn consume_messages(
         match ev {
             NetworkEvent::Message(handle, raw_ev) => {
                 trace!("consuming message from {:?}", handle);
-                if let Ok(enveloppe) = serde_json::from_reader::<std::io::Cursor<Vec<u8>>, Enveloppe>(
-                    std::io::Cursor::new(raw_ev),
-                ) {
-                    let tp = enveloppe.message_type.to_string();
-                    let mut v = if let Some(x) = hmap.remove(&tp) {
-                        x
-                    } else {
-                        Vec::new()
-                    };
-                    v.push((handle, enveloppe.clone()));
-                    hmap.insert(tp, v);
-                } else {
-                    warn!("failed to deserialize message from {:?}", handle);
-                    continue;
+                match Enveloppe::decode(server.codec_for(&handle), raw_ev) {
+                    Ok(enveloppe) if router.lock().unwrap().has_type(&enveloppe.message_type) => {
+                        let tp = enveloppe.message_type.to_string();
+                        let mut v = hmap.remove(&tp).unwrap_or_default();
+                        v.push((handle, enveloppe));
+                        hmap.insert(tp, v);
+                    }
+                    Ok(enveloppe) => {
+                        warn!(
+                            "received unregistered type '{}' from {:?}",
+                            enveloppe.message_type, handle
+                        );
+                        if let Some(errors) = errors.as_mut() {
+                            let error = ErrorMessage::new(
+                                ErrorMessage::UNKNOWN_TYPE,
+                                format!("type '{}' not registered", enveloppe.message_type),
+                            );
+                            errors.push(handle, &enveloppe, error);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("failed to deserialize message from {:?}", handle);
+                        if let Some(errors) = errors.as_mut() {
+                            let error = ErrorMessage::new(ErrorMessage::MALFORMED, e.to_string());
+                            errors.0.push((handle, None, error));
+                        }
+                    }
                 }
             }
             other => {
@@ -62,3 +99,102 @@ fn consume_messages(
     }
 }
 
+fn send_error_reports(server: Res<Server>, mut errors: ResMut<ErrorReports>) {
+    for (handle, correlation_id, error) in errors.0.drain(..) {
+        // the nil handle belongs to the client plugin
+        if handle.uuid.is_nil() {
+            continue;
+        }
+        if let Err(e) = server.send_error(&handle, correlation_id, &error) {
+            debug!("failed to report error to {:?}: {}", handle, e);
+        }
+    }
+}
+
+fn update_round_trip_times(server: Res<Server>, mut rtts: ResMut<RoundTripTimes>) {
+    // the nil handle belongs to the client plugin
+    rtts.0.retain(|handle, _| handle.uuid.is_nil());
+    rtts.0.extend(server.rtts());
+}
+
+/// Connections an [`Outgoing`] message is sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recipients {
+    One(ConnectionHandle),
+    Many(Vec<ConnectionHandle>),
+    /// Members of a room, see `Server::join`.
+    Room(String),
+    All,
+    AllExcept(ConnectionHandle),
+}
+
+/// Message sent by writing it to an `EventWriter<Outgoing<T>>`, once registered with
+/// `add_outgoing_type::<T>()`.
+///
+/// The messages written during a frame are sent together at its end, in the `network_flush`
+/// stage. Failures are logged.
+#[derive(Debug, Clone)]
+pub struct Outgoing<T> {
+    pub recipients: Recipients,
+    pub message: T,
+}
+
+impl<T> Outgoing<T> {
+    pub fn to(handle: ConnectionHandle, message: T) -> Self {
+        Self {
+            recipients: Recipients::One(handle),
+            message,
+        }
+    }
+
+    pub fn to_many(handles: Vec<ConnectionHandle>, message: T) -> Self {
+        Self {
+            recipients: Recipients::Many(handles),
+            message,
+        }
+    }
+
+    pub fn to_room(room: impl Into<String>, message: T) -> Self {
+        Self {
+            recipients: Recipients::Room(room.into()),
+            message,
+        }
+    }
+
+    pub fn to_all(message: T) -> Self {
+        Self {
+            recipients: Recipients::All,
+            message,
+        }
+    }
+
+    pub fn to_all_except(handle: ConnectionHandle, message: T) -> Self {
+        Self {
+            recipients: Recipients::AllExcept(handle),
+            message,
+        }
+    }
+}
+
+pub(crate) fn send_outgoing<T>(server: Res<Server>, mut outgoing: EventReader<Outgoing<T>>)
+where
+    T: MessageType + Serialize + Clone,
+{
+    for Outgoing {
+        recipients,
+        message,
+    } in outgoing.iter()
+    {
+        match recipients {
+            Recipients::One(handle) => {
+                if let Err(e) = server.send_message(handle, message) {
+                    warn!("failed to send {} to {:?}: {}", T::message_type(), handle, e);
+                }
+            }
+            Recipients::Many(handles) => server.broadcast_to(handles, message.clone()),
+            Recipients::Room(room) => server.broadcast_to_room(room, message.clone()),
+            Recipients::All => server.broadcast(message.clone()),
+            Recipients::AllExcept(handle) => server.broadcast_except(handle, message.clone()),
+        }
+    }
+}
@@ -1,9 +1,56 @@
-use crate::shared::{ConnectionHandle, Enveloppe, GenericParser, MessageType, NetworkEvent};
+use crate::shared::{
+    ConnectionHandle, Enveloppe, ErrorMessage, GenericParser, MessageType, NetworkEvent, RttStats,
+    SharedRuntime,
+};
 use bevy::prelude::*;
 use log::warn;
+#[cfg(feature = "server")]
+use serde::Serialize;
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
 
+/// Round-trip times of the open connections, refreshed every frame while keepalive is enabled.
+#[derive(Default, Debug)]
+pub struct RoundTripTimes(pub HashMap<ConnectionHandle, RttStats>);
+
+impl RoundTripTimes {
+    pub fn get(&self, handle: &ConnectionHandle) -> Option<&RttStats> {
+        self.0.get(handle)
+    }
+}
+
+/// Runtime of the app, inserted by the first network plugin and reused by the others. Insert
+/// one before adding the plugins to run them on an existing runtime.
+pub(crate) fn shared_runtime(app: &mut AppBuilder, worker_threads: Option<NonZeroUsize>) -> SharedRuntime {
+    if let Some(rt) = app.world().get_resource::<SharedRuntime>() {
+        return rt.clone();
+    }
+    let rt = SharedRuntime::build(worker_threads);
+    app.insert_resource(rt.clone());
+    rt
+}
+
+/// Resources, events and stages shared by the server and client plugins, added by the first one.
+pub(crate) fn network_stage(app: &mut AppBuilder) -> &mut AppBuilder {
+    if app
+        .app
+        .schedule
+        .get_stage::<SystemStage>(&"network")
+        .is_none()
+    {
+        app.insert_resource(Arc::new(Mutex::new(GenericParser::new())))
+            .insert_resource(HashMap::<String, Vec<(ConnectionHandle, Enveloppe)>>::new())
+            .insert_resource(Vec::<NetworkEvent>::new())
+            .insert_resource(RoundTripTimes::default())
+            .add_event::<NetworkEvent>()
+            .add_stage_before(CoreStage::First, "network", SystemStage::single_threaded())
+            .add_stage_after(CoreStage::Last, "network_flush", SystemStage::single_threaded())
+            .add_system_to_stage("network", handle_network_events.system());
+    }
+    app
+}
+
 pub(crate) fn handle_network_events(
     mut events: ResMut<Vec<NetworkEvent>>,
     mut sink: EventWriter<NetworkEvent>,
@@ -13,31 +60,99 @@ pub(crate) fn handle_network_events(
     }
 }
 
+/// Message sent with `Client::request`, to be answered with `Server::respond(&handle, id, ..)`.
+#[derive(Debug)]
+pub struct IncomingRequest<T> {
+    pub handle: ConnectionHandle,
+    /// Correlation id of the request.
+    pub id: u64,
+    pub payload: T,
+}
+
+/// Errors to send back to the connections whose messages couldn't be delivered, present when
+/// `ServerConfig::report_errors` is enabled.
+#[derive(Default)]
+pub(crate) struct ErrorReports(pub(crate) Vec<(ConnectionHandle, Option<u64>, ErrorMessage)>);
+
+impl ErrorReports {
+    pub(crate) fn push(
+        &mut self,
+        handle: ConnectionHandle,
+        enveloppe: &Enveloppe,
+        error: ErrorMessage,
+    ) {
+        self.0.push((
+            handle,
+            enveloppe.correlation_id,
+            error.with_type(enveloppe.message_type.clone()),
+        ));
+    }
+}
+
+fn parse_message<T: 'static>(
+    router: &GenericParser,
+    handle: &ConnectionHandle,
+    enveloppe: &Enveloppe,
+    errors: &mut Option<ResMut<ErrorReports>>,
+) -> Option<T> {
+    let parsed = router
+        .parse_enveloppe(enveloppe)
+        .and_then(GenericParser::try_into_concrete_type::<T>);
+    match parsed {
+        Ok(msg) => Some(msg),
+        Err(e) => {
+            warn!("failed to parse type enveloppe : {}", e);
+            if let Some(errors) = errors {
+                let error = ErrorMessage::new(ErrorMessage::INVALID_PAYLOAD, e.to_string());
+                errors.push(handle.clone(), enveloppe, error);
+            }
+            None
+        }
+    }
+}
+
 pub(crate) fn add_message_consumer<T>(
     key: Local<String>,
     mut hmap: ResMut<HashMap<String, Vec<(ConnectionHandle, Enveloppe)>>>,
     router: Res<Arc<Mutex<GenericParser>>>,
+    mut errors: Option<ResMut<ErrorReports>>,
     mut queue: EventWriter<(ConnectionHandle, T)>,
 ) where
     T: Send + Sync + 'static,
 {
     if let Some(values) = hmap.remove(&*key) {
+        let router = router.lock().unwrap();
         for (handle, v) in values {
-            let enveloppe = router.lock().unwrap().parse_enveloppe(&v);
-            match enveloppe {
-                Ok(dat) => match GenericParser::try_into_concrete_type::<T>(dat) {
-                    Ok(msg) => {
-                        queue.send((handle, msg));
-                    }
-                    Err(e) => {
-                        warn!("failed to downcast : {}", e);
-                    }
-                },
-                Err(e) => {
-                    warn!("failed to parse type enveloppe : {}", e);
-                    continue;
+            if let Some(msg) = parse_message(&router, &handle, &v, &mut errors) {
+                queue.send((handle, msg));
+            }
+        }
+    }
+}
+
+pub(crate) fn add_request_consumer<T>(
+    key: Local<String>,
+    mut hmap: ResMut<HashMap<String, Vec<(ConnectionHandle, Enveloppe)>>>,
+    router: Res<Arc<Mutex<GenericParser>>>,
+    mut errors: Option<ResMut<ErrorReports>>,
+    mut messages: EventWriter<(ConnectionHandle, T)>,
+    mut requests: EventWriter<IncomingRequest<T>>,
+) where
+    T: Send + Sync + 'static,
+{
+    if let Some(values) = hmap.remove(&*key) {
+        let router = router.lock().unwrap();
+        for (handle, v) in values {
+            if let Some(payload) = parse_message(&router, &handle, &v, &mut errors) {
+                match v.correlation_id {
+                    Some(id) => requests.send(IncomingRequest {
+                        handle,
+                        id,
+                        payload,
+                    }),
+                    None => messages.send((handle, payload)),
                 }
-            };
+            }
         }
     }
 }
@@ -56,6 +171,22 @@ pub trait WsMessageInserter {
     fn add_message_type<T>(&mut self) -> &mut Self
     where
         T: MessageType + 'static;
+    /// Same as [`add_message_type`](#tymethod.add_message_type), but the messages carrying a
+    /// correlation id are emitted as `IncomingRequest<T>` events.
+    fn add_request_type<T>(&mut self) -> &mut Self
+    where
+        T: MessageType + 'static;
+    /// Emit the responses pushed to the `PendingResponses<T>` resource as
+    /// `IncomingResponse<T>` events.
+    #[cfg(feature = "client")]
+    fn add_response_type<T>(&mut self) -> &mut Self
+    where
+        T: MessageType + 'static;
+    /// Send the `Outgoing<T>` events written during a frame at its end.
+    #[cfg(feature = "server")]
+    fn add_outgoing_type<T>(&mut self) -> &mut Self
+    where
+        T: MessageType + Serialize + Clone + 'static;
 }
 
 impl WsMessageInserter for AppBuilder {
@@ -76,4 +207,48 @@ impl WsMessageInserter for AppBuilder {
         }));
         self
     }
+
+    fn add_request_type<T>(&mut self) -> &mut Self
+    where
+        T: MessageType + 'static,
+    {
+        self.add_event::<(ConnectionHandle, T)>()
+            .add_event::<IncomingRequest<T>>();
+        let router = self
+            .app
+            .world
+            .get_resource::<Arc<Mutex<GenericParser>>>()
+            .expect("cannot register message before WebSocketServer initialization");
+        router.lock().unwrap().insert_type::<T>();
+
+        self.add_system(add_request_consumer::<T>.system().config(|params| {
+            params.0 = Some(T::message_type().to_string());
+        }));
+        self
+    }
+
+    #[cfg(feature = "client")]
+    fn add_response_type<T>(&mut self) -> &mut Self
+    where
+        T: MessageType + 'static,
+    {
+        use super::plugin_client::{poll_responses, IncomingResponse, PendingResponses};
+        self.add_event::<IncomingResponse<T>>()
+            .insert_resource(PendingResponses::<T>::default())
+            .add_system_to_stage("network", poll_responses::<T>.system())
+    }
+
+    #[cfg(feature = "server")]
+    fn add_outgoing_type<T>(&mut self) -> &mut Self
+    where
+        T: MessageType + Serialize + Clone + 'static,
+    {
+        use super::plugin_server::{send_outgoing, Outgoing};
+        self.app
+            .world
+            .get_resource::<crate::server::Server>()
+            .expect("cannot register outgoing message before WebSocketServer initialization");
+        self.add_event::<Outgoing<T>>()
+            .add_system_to_stage("network_flush", send_outgoing::<T>.system())
+    }
 }
@@ -0,0 +1,115 @@
+use crate::shared::{Codec, FrameKind, JsonCodec, KeepaliveConfig, MessageLimits};
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+use super::{ConnectOptions, ReconnectPolicy};
+#[cfg(feature = "tls")]
+use super::ClientTlsConfig;
+
+/// Settings of a [`Client`](super::Client), built by chaining its methods on
+/// `ClientConfig::default()`.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub(crate) endpoint: Option<String>,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<ClientTlsConfig>,
+    pub(crate) worker_threads: Option<NonZeroUsize>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) connect: ConnectOptions,
+    pub(crate) reconnect: Option<ReconnectPolicy>,
+    pub(crate) request_timeout: Duration,
+    pub(crate) keepalive: Option<KeepaliveConfig>,
+    pub(crate) limits: MessageLimits,
+    pub(crate) frame_kind: FrameKind,
+    pub(crate) codec: Arc<dyn Codec>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            worker_threads: None,
+            connect_timeout: None,
+            connect: ConnectOptions::default(),
+            reconnect: None,
+            request_timeout: Duration::from_secs(30),
+            keepalive: None,
+            limits: MessageLimits::default(),
+            frame_kind: FrameKind::default(),
+            codec: Arc::new(JsonCodec),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// URL dialed by [`Client::start`](super::Client::start). The Bevy plugin connects to it at
+    /// startup.
+    pub fn endpoint(mut self, url: impl Into<String>) -> Self {
+        self.endpoint = Some(url.into());
+        self
+    }
+
+    /// Trust settings used for `wss://` endpoints.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: ClientTlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Number of threads of the tokio runtime, one per core by default. Ignored when the
+    /// runtime is shared.
+    pub fn worker_threads(mut self, threads: NonZeroUsize) -> Self {
+        self.worker_threads = Some(threads);
+        self
+    }
+
+    /// Give up on a connection attempt that doesn't complete its handshakes in time.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Headers, query parameters and subprotocols of the handshake request sent by
+    /// [`Client::connect`](super::Client::connect).
+    pub fn connect_options(mut self, options: ConnectOptions) -> Self {
+        self.connect = options;
+        self
+    }
+
+    /// Re-dial automatically when the connection fails or drops.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// How long [`Client::request`](super::Client::request) waits for a response, 30 seconds
+    /// by default.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Ping the server and drop the connection when it stops answering.
+    pub fn keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Size limits of the messages received from the server.
+    pub fn limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Frame type of the messages sent to the server, binary by default.
+    pub fn frame_kind(mut self, frame_kind: FrameKind) -> Self {
+        self.frame_kind = frame_kind;
+        self
+    }
+
+    /// Wire format of the messages, JSON by default.
+    pub fn codec(mut self, codec: impl Codec) -> Self {
+        self.codec = Arc::new(codec);
+        self
+    }
+}
@@ -1,2 +1,15 @@
+mod config;
+mod options;
+mod reconnect;
+mod rpc;
 mod ws_client;
+#[cfg(feature = "tls")]
+mod tls;
+
+pub use config::ClientConfig;
+pub use options::ConnectOptions;
+pub use reconnect::*;
+pub use rpc::PendingResponse;
 pub use ws_client::*;
+#[cfg(feature = "tls")]
+pub use tls::*;
@@ -0,0 +1,79 @@
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::http::{
+    header::SEC_WEBSOCKET_PROTOCOL, HeaderMap, HeaderName, HeaderValue,
+};
+use super::ClientConfigError;
+
+/// Extra parameters of the handshake request sent by [`Client::connect_with`](super::Client::connect_with).
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    headers: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    subprotocols: Vec<String>,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl ConnectOptions {
+    /// Add a header to the upgrade request, e.g. credentials or an API key.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send `Authorization: Bearer <token>`.
+    pub fn bearer_token(self, token: impl AsRef<str>) -> Self {
+        self.header("Authorization", format!("Bearer {}", token.as_ref()))
+    }
+
+    /// Append a parameter to the query string of the endpoint, for servers expecting the
+    /// credentials there, such as browsers which can't set headers.
+    pub fn query(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// Also offer `protocol` to the server, after the subprotocol of the codec.
+    /// [`Client::connect_with`](super::Client::connect_with) refuses protocols which aren't
+    /// valid in a header.
+    pub fn subprotocol(mut self, protocol: impl Into<String>) -> Self {
+        self.subprotocols.push(protocol.into());
+        self
+    }
+
+    /// Give up on a connection attempt that doesn't complete its handshakes in time, instead
+    /// of [`ClientConfig::connect_timeout`](super::ClientConfig::connect_timeout).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub(crate) fn endpoint(&self, endpoint: &str) -> Result<url::Url, ClientConfigError> {
+        let mut url = url::Url::parse(endpoint)?;
+        if !self.query.is_empty() {
+            url.query_pairs_mut().extend_pairs(&self.query);
+        }
+        Ok(url)
+    }
+
+    pub(crate) fn header_map(&self) -> Result<HeaderMap, ClientConfigError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let invalid = || ClientConfigError::InvalidHeader(name.clone());
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?,
+                HeaderValue::from_str(value).map_err(|_| invalid())?,
+            );
+        }
+        Ok(headers)
+    }
+
+    /// `Sec-WebSocket-Protocol` header offering `codec` first, then the extra subprotocols.
+    pub(crate) fn protocol_header(&self, codec: &str) -> Result<HeaderValue, ClientConfigError> {
+        let offered = std::iter::once(codec)
+            .chain(self.subprotocols.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(", ");
+        HeaderValue::from_str(&offered)
+            .map_err(|_| ClientConfigError::InvalidHeader(SEC_WEBSOCKET_PROTOCOL.to_string()))
+    }
+}
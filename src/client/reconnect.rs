@@ -0,0 +1,109 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// What to do with messages sent while the client is not connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutageQueuePolicy {
+    /// Keep the messages and send them once the connection is re-established.
+    Buffer,
+    /// Discard the messages sent during the outage.
+    Drop,
+}
+
+/// Exponential backoff used by [`Client`](super::Client) to re-dial after a failure.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnection attempt.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+    /// Random variation applied to each delay, as a fraction of it (`0.2` is ±20%).
+    pub jitter: f64,
+    /// Give up after this many consecutive failed attempts, retry forever if `None`.
+    pub max_attempts: Option<u32>,
+    pub queue_policy: OutageQueuePolicy,
+    /// Also re-dial after the server closed the connection on purpose, with a `Normal`,
+    /// `Policy` or `Away` close code, e.g. when it kicked the client.
+    pub reconnect_after_close: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            max_attempts: None,
+            queue_policy: OutageQueuePolicy::Buffer,
+            reconnect_after_close: false,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay to wait before the given attempt, starting at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let base = self
+            .initial_delay
+            .checked_mul(1 << exp)
+            .unwrap_or(Duration::MAX);
+        // a NaN jitter is treated as no jitter
+        let jitter = if self.jitter.is_nan() {
+            0.0
+        } else {
+            self.jitter.clamp(0.0, 1.0)
+        };
+        if jitter == 0.0 {
+            return base.min(self.max_delay);
+        }
+        // jittered before capping, so the delay never exceeds `max_delay`
+        let secs = base.as_secs_f64() * (1.0 + rand::thread_rng().gen_range(-jitter..=jitter));
+        if secs >= self.max_delay.as_secs_f64() {
+            self.max_delay
+        } else {
+            Duration::from_secs_f64(secs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delay_doubles_until_the_cap() {
+        let policy = policy(0.0);
+        let delays: Vec<_> = (1..=6).map(|attempt| policy.delay(attempt).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds_and_under_the_cap() {
+        let policy = policy(0.5);
+        for _ in 0..1000 {
+            let first = policy.delay(1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(150));
+            let capped = policy.delay(10);
+            assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn zero_or_nan_jitter_is_deterministic() {
+        for jitter in [0.0, -1.0, f64::NAN] {
+            assert_eq!(policy(jitter).delay(2), Duration::from_millis(200));
+        }
+    }
+}
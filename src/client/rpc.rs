@@ -0,0 +1,155 @@
+use crate::shared::{Enveloppe, ErrorMessage, MessageType, NetworkError};
+use log::debug;
+use std::{
+    collections::HashMap,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    task::{Context, Poll},
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+type Reply = Result<Enveloppe, NetworkError>;
+
+struct Waiting {
+    tx: oneshot::Sender<Reply>,
+    /// Fails the request once its timeout elapses, aborted when it completes first.
+    timer: Option<JoinHandle<()>>,
+}
+
+impl Waiting {
+    fn complete(self, reply: Reply) {
+        if let Some(timer) = self.timer {
+            timer.abort();
+        }
+        self.tx.send(reply).ok();
+    }
+}
+
+/// Requests of a client waiting for their response, by correlation id.
+#[derive(Default)]
+pub(crate) struct PendingRequests {
+    next_id: AtomicU64,
+    waiting: Mutex<HashMap<u64, Waiting>>,
+}
+
+impl PendingRequests {
+    pub(crate) fn register(&self) -> (u64, oneshot::Receiver<Reply>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.waiting
+            .lock()
+            .unwrap()
+            .insert(id, Waiting { tx, timer: None });
+        (id, rx)
+    }
+
+    /// Attach the timeout task of request `id`, aborting it if the request already completed.
+    pub(crate) fn set_timer(&self, id: u64, timer: JoinHandle<()>) {
+        match self.waiting.lock().unwrap().get_mut(&id) {
+            Some(waiting) => waiting.timer = Some(timer),
+            None => timer.abort(),
+        }
+    }
+
+    /// Hand a received response to the request it answers, or give the message back if it
+    /// isn't a response. Late responses, whose request already timed out or was dropped, are
+    /// discarded.
+    pub(crate) fn resolve(&self, enveloppe: Enveloppe) -> Option<Enveloppe> {
+        let id = match enveloppe.correlation_id {
+            Some(id) => id,
+            None => return Some(enveloppe),
+        };
+        let waiting = self.waiting.lock().unwrap().remove(&id);
+        match waiting {
+            Some(waiting) => waiting.complete(Ok(enveloppe)),
+            None => debug!(
+                "dropping {} response to unknown or expired request {}",
+                enveloppe.message_type, id
+            ),
+        }
+        None
+    }
+
+    pub(crate) fn fail(&self, id: u64, e: NetworkError) {
+        if let Some(waiting) = self.waiting.lock().unwrap().remove(&id) {
+            waiting.complete(Err(e));
+        }
+    }
+
+    /// Drop every pending request, which then fails with `NetworkError::NotConnected`.
+    pub(crate) fn clear(&self) {
+        for (_, waiting) in self.waiting.lock().unwrap().drain() {
+            if let Some(timer) = waiting.timer {
+                timer.abort();
+            }
+        }
+    }
+}
+
+/// Response to a request sent with [`Client::request`](super::Client::request).
+///
+/// Await it, or poll it with [`try_recv`](#method.try_recv) from a game loop. Fails with
+/// `NetworkError::Remote` when the server answered with an [`ErrorMessage`], and with
+/// `NetworkError::RequestTimeout` when it didn't answer in time.
+#[derive(Debug)]
+pub struct PendingResponse<T> {
+    id: u64,
+    rx: oneshot::Receiver<Reply>,
+    _response: PhantomData<fn() -> T>,
+}
+
+impl<T: MessageType> PendingResponse<T> {
+    pub(crate) fn new(id: u64, rx: oneshot::Receiver<Reply>) -> Self {
+        Self {
+            id,
+            rx,
+            _response: PhantomData,
+        }
+    }
+
+    /// Correlation id of the request.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The response if it arrived, `None` while still waiting.
+    pub fn try_recv(&mut self) -> Option<Result<T, NetworkError>> {
+        match self.rx.try_recv() {
+            Ok(reply) => Some(into_response(reply)),
+            Err(oneshot::error::TryRecvError::Empty) => None,
+            Err(oneshot::error::TryRecvError::Closed) => Some(Err(NetworkError::NotConnected)),
+        }
+    }
+}
+
+impl<T: MessageType> Future for PendingResponse<T> {
+    type Output = Result<T, NetworkError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx).poll(cx).map(|reply| match reply {
+            Ok(reply) => into_response(reply),
+            Err(_) => Err(NetworkError::NotConnected),
+        })
+    }
+}
+
+fn into_response<T: MessageType>(reply: Reply) -> Result<T, NetworkError> {
+    let enveloppe = reply?;
+    if enveloppe.message_type == ErrorMessage::message_type() {
+        let error = enveloppe.payload().map_err(NetworkError::Decode)?;
+        return Err(NetworkError::Remote(error));
+    }
+    if enveloppe.message_type != T::message_type() {
+        return Err(NetworkError::Decode(anyhow::anyhow!(
+            "expected a {} response, received {}",
+            T::message_type(),
+            enveloppe.message_type
+        )));
+    }
+    enveloppe.payload().map_err(NetworkError::Decode)
+}
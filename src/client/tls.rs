@@ -0,0 +1,98 @@
+use super::ClientConfigError;
+use std::{fmt, fs, io::BufReader, path::Path, sync::Arc};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+/// Trust settings used when connecting to `wss://` endpoints.
+///
+/// By default, only the Mozilla root certificates bundled with `webpki-roots` are trusted.
+#[derive(Clone)]
+pub struct ClientTlsConfig {
+    roots: RootCertStore,
+    #[cfg(feature = "dangerous-tls")]
+    accept_invalid_certs: bool,
+}
+
+impl Default for ClientTlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ClientTlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientTlsConfig").finish_non_exhaustive()
+    }
+}
+
+impl ClientTlsConfig {
+    pub fn new() -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        Self {
+            roots,
+            #[cfg(feature = "dangerous-tls")]
+            accept_invalid_certs: false,
+        }
+    }
+
+    /// Trust the PEM encoded root certificates, e.g. an internal CA.
+    pub fn add_root_certificate_pem(&mut self, pem: &[u8]) -> Result<(), ClientConfigError> {
+        match self.roots.add_pem_file(&mut BufReader::new(pem)) {
+            Ok((valid, _)) if valid > 0 => Ok(()),
+            _ => Err(ClientConfigError::InvalidCertificate),
+        }
+    }
+
+    /// Trust the root certificates found in a PEM file.
+    pub fn add_root_certificate_pem_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), ClientConfigError> {
+        self.add_root_certificate_pem(&fs::read(path)?)
+    }
+
+    /// Skip server certificate verification entirely, so self-signed certificates are accepted.
+    ///
+    /// This makes the connection vulnerable to man-in-the-middle attacks and must only be used
+    /// in development builds.
+    #[cfg(feature = "dangerous-tls")]
+    pub fn danger_accept_invalid_certs(&mut self, accept: bool) {
+        self.accept_invalid_certs = accept;
+    }
+
+    pub(crate) fn connector(&self) -> Arc<ClientConfig> {
+        let mut config = ClientConfig::new();
+        config.root_store = self.roots.clone();
+        #[cfg(feature = "dangerous-tls")]
+        if self.accept_invalid_certs {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(danger::NoCertificateVerification));
+        }
+        Arc::new(config)
+    }
+}
+
+#[cfg(feature = "dangerous-tls")]
+mod danger {
+    use tokio_rustls::{
+        rustls::{
+            Certificate, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError,
+        },
+        webpki::DNSNameRef,
+    };
+
+    pub(super) struct NoCertificateVerification;
+
+    impl ServerCertVerifier for NoCertificateVerification {
+        fn verify_server_cert(
+            &self,
+            _roots: &RootCertStore,
+            _presented_certs: &[Certificate],
+            _dns_name: DNSNameRef,
+            _ocsp_response: &[u8],
+        ) -> Result<ServerCertVerified, TLSError> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+}
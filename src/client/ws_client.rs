@@ -1,18 +1,95 @@
 use serde::{Serialize};
-use crossbeam_channel::{unbounded, TryRecvError, Receiver, Sender};
-use futures::{join, SinkExt, StreamExt};
-use log::{error, warn};
-use std::sync::Arc;
-use tokio::{runtime::Runtime, task::JoinHandle};
-use tokio_tungstenite::connect_async;
+use crossbeam_channel::TryRecvError;
+use futures::{
+    future::{select, Either},
+    pin_mut,
+    stream::{self, Stream},
+    SinkExt, StreamExt,
+};
+use log::{debug, error, warn};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+use thiserror::Error as TError;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    runtime::Handle,
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{
+    connect_async_with_config,
+    MaybeTlsStream,
+    tungstenite::{
+        handshake::client::Request,
+        http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderMap, HeaderValue},
+        protocol::CloseFrame,
+        Error as TungsteniteError,
+        Message,
+    },
+};
 
-use crate::shared::{ConnectionHandle, NetworkEvent, SendEnveloppe, MessageType};
+use crate::shared::{
+    event_channel, with_handshake_deadline, CloseCode, Codec, ConnectionHandle, ConnectionInfo,
+    DisconnectReason,
+    Enveloppe, EventReceiver, EventSender, FrameKind, GenericParser, Heartbeat, Keepalive,
+    KeepaliveConfig, MessageLimits, MessageType, NetworkError, NetworkEvent, RttStats,
+    SharedRuntime, CLOSE_TIMEOUT,
+};
+use super::{
+    rpc::PendingRequests, ClientConfig, ConnectOptions, OutageQueuePolicy, PendingResponse,
+    ReconnectPolicy,
+};
+#[cfg(feature = "tls")]
+use super::ClientTlsConfig;
+
+#[derive(TError, Debug)]
+pub enum ClientConfigError {
+    #[error("invalid endpoint URL: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("no endpoint configured")]
+    MissingEndpoint,
+    #[error("invalid handshake header {0:?}")]
+    InvalidHeader(String),
+    #[error("failed to read TLS material: {0}")]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "tls")]
+    #[error("no valid PEM certificate found")]
+    InvalidCertificate,
+}
 
 pub struct Client {
-    rt: Arc<Runtime>,
+    rt: SharedRuntime,
     handle: Option<JoinHandle<()>>,
-    rx: Option<Arc<Receiver<NetworkEvent>>>,
-    tx: Option<Arc<Sender<tokio_tungstenite::tungstenite::Message>>>,
+    rx: Option<Arc<EventReceiver<NetworkEvent>>>,
+    tx: Option<mpsc::UnboundedSender<Message>>,
+    closing: Arc<AtomicBool>,
+    /// Wakes up the connection task waiting to reconnect when `closing` is set.
+    close_requested: Arc<Notify>,
+    session_keepalive: Arc<Mutex<Option<Arc<Keepalive>>>>,
+    session_info: Arc<Mutex<Option<Arc<ConnectionInfo>>>>,
+    requests: Arc<PendingRequests>,
+    config: ClientConfig,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if let Some(mut task) = self.handle.take() {
+            self.close(CloseCode::Normal, "").ok();
+            // let the session send the close frame, but don't keep it around
+            self.rt.handle().spawn(async move {
+                if tokio::time::timeout(CLOSE_TIMEOUT, &mut task).await.is_err() {
+                    task.abort();
+                }
+            });
+        }
+    }
 }
 
 impl Default for Client {
@@ -27,82 +104,325 @@ impl Client {
     }
 
     pub fn new() -> Client {
+        Self::with_config(ClientConfig::default())
+    }
+
+    /// Client running on its own runtime, with [`ClientConfig::worker_threads`] threads.
+    pub fn with_config(config: ClientConfig) -> Client {
+        let rt = SharedRuntime::build(config.worker_threads);
+        Self::with_runtime(config, rt)
+    }
+
+    /// Client running on an existing runtime, e.g. the one of the application.
+    pub fn with_handle(config: ClientConfig, handle: Handle) -> Client {
+        Self::with_runtime(config, SharedRuntime::from_handle(handle))
+    }
+
+    /// Client running on a runtime shared with other servers and clients.
+    pub fn with_runtime(config: ClientConfig, rt: SharedRuntime) -> Client {
         Client {
-            rt: Arc::new(
-                tokio::runtime::Builder::new_multi_thread()
-                    .enable_all()
-                    .build()
-                    .expect("Could not build tokio runtime"),
-            ),
+            rt,
             handle: None,
             rx: None,
-            tx: None
+            tx: None,
+            closing: Arc::new(AtomicBool::new(false)),
+            close_requested: Arc::new(Notify::new()),
+            session_keepalive: Arc::new(Mutex::new(None)),
+            session_info: Arc::new(Mutex::new(None)),
+            requests: Arc::new(PendingRequests::default()),
+            config,
         }
     }
 
-    pub fn connect(&mut self, endpoint: String) {
-        let (ev_tx, ev_rx) = unbounded();
-        let (from_handler_tx, from_handler_rx) = unbounded();
+    /// Settings of the client, including the changes made through its setters.
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Trust settings used for `wss://` endpoints on the next call to [`connect`](#method.connect).
+    #[cfg(feature = "tls")]
+    pub fn set_tls_config(&mut self, tls: &ClientTlsConfig) {
+        self.config.tls = Some(tls.clone());
+    }
+
+    /// Re-dial automatically when the connection fails or drops, with the given backoff.
+    ///
+    /// Takes effect on the next call to [`connect`](#method.connect).
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) {
+        self.config.reconnect = policy;
+    }
+
+    /// Ping the server and drop the connection when it stops answering.
+    ///
+    /// Takes effect on the next call to [`connect`](#method.connect).
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) {
+        self.config.keepalive = keepalive;
+    }
+
+    /// Size limits of the messages received from the server.
+    ///
+    /// Takes effect on the next call to [`connect`](#method.connect).
+    pub fn set_limits(&mut self, limits: MessageLimits) {
+        self.config.limits = limits;
+    }
+
+    /// Smoothed round-trip time to the server, available once keepalive is enabled and the
+    /// first Pong was received.
+    pub fn rtt(&self) -> Option<RttStats> {
+        self.session_keepalive
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|k| k.rtt())
+    }
+
+    /// What is known about the current connection, `None` while disconnected.
+    pub fn connection_info(&self) -> Option<Arc<ConnectionInfo>> {
+        self.session_info.lock().unwrap().clone()
+    }
+
+    /// Frame type used by [`send_message`](#method.send_message), binary by default.
+    pub fn set_frame_kind(&mut self, frame_kind: FrameKind) {
+        self.config.frame_kind = frame_kind;
+    }
+
+    /// Wire format of the messages, JSON by default. Both ends must use the same codec.
+    pub fn set_codec(&mut self, codec: impl Codec) {
+        self.config.codec = Arc::new(codec);
+    }
+
+    pub fn codec(&self) -> Arc<dyn Codec> {
+        self.config.codec.clone()
+    }
+
+    /// Connect to the URL given to [`ClientConfig::endpoint`].
+    pub fn start(&mut self) -> Result<(), ClientConfigError> {
+        let endpoint = self
+            .config
+            .endpoint
+            .clone()
+            .ok_or(ClientConfigError::MissingEndpoint)?;
+        self.connect(endpoint)
+    }
+
+    /// Connect with the options given to [`ClientConfig::connect_options`].
+    pub fn connect(&mut self, endpoint: String) -> Result<(), ClientConfigError> {
+        let options = self.config.connect.clone();
+        self.connect_with(endpoint, options)
+    }
+
+    /// Connect with extra headers, query parameters or subprotocols, e.g. credentials.
+    pub fn connect_with(
+        &mut self,
+        endpoint: String,
+        options: ConnectOptions,
+    ) -> Result<(), ClientConfigError> {
+        let s = options.endpoint(&endpoint)?;
+        let headers = options.header_map()?;
+        let codec = self.config.codec.clone();
+        let protocols = options.protocol_header(&codec.subprotocol())?;
+        let (ev_tx, ev_rx) = event_channel();
+        let (from_handler_tx, mut from_handler_rx) = mpsc::unbounded_channel();
+        #[cfg(feature = "tls")]
+        let tls = self.config.tls.as_ref().map(ClientTlsConfig::connector);
+        let reconnect = self.config.reconnect.clone();
+        let keepalive = self.config.keepalive.clone();
+        let connect_timeout = options.timeout.or(self.config.connect_timeout);
+        let current_keepalive = self.session_keepalive.clone();
+        let current_info = self.session_info.clone();
+        let requests = self.requests.clone();
+        self.closing = Arc::new(AtomicBool::new(false));
+        self.close_requested = Arc::new(Notify::new());
+        let closing = self.closing.clone();
+        let close_requested = self.close_requested.clone();
+        let websocket = self.config.limits.websocket_config();
 
         let event_loop = async move {
-            let s = url::Url::parse(&endpoint).expect("failed to parse connect URL");
-            let (ws_stream, _) = connect_async(s).await.expect("Failed to connect");
-            let (mut write, read) = ws_stream.split();
-            ev_tx
-                .send(NetworkEvent::Connected(ConnectionHandle {
-                    uuid: uuid::Uuid::nil(),
-                }))
-                .expect("failed to send network event");
-            let read_handle = async move {
-                read.for_each(|msg| async {
-                    match msg {
-                        Err(e) => {
-                            error!("failed to receive message: {:?}", e);
+            let handle = ConnectionHandle {
+                uuid: uuid::Uuid::nil(),
+            };
+            let mut attempt = 0;
+            loop {
+                // set when the server closed the connection on purpose
+                let mut closed_by_server = false;
+                if closing.load(Ordering::Acquire) {
+                    break;
+                }
+                let request = client_request(&s, &headers, &protocols);
+                let uri = request.uri().clone();
+                let deadline = connect_timeout.map(|limit| tokio::time::Instant::now() + limit);
+                #[cfg(feature = "tls")]
+                let dialed = match &tls {
+                    Some(tls) => {
+                        let dial = connect_tls(&s, request, websocket, tls.clone());
+                        with_handshake_deadline(deadline, dial).await
+                    }
+                    None => {
+                        let dial = connect_async_with_config(request, Some(websocket));
+                        with_handshake_deadline(deadline, dial).await
+                    }
+                };
+                #[cfg(not(feature = "tls"))]
+                let dialed = with_handshake_deadline(
+                    deadline,
+                    connect_async_with_config(request, Some(websocket)),
+                )
+                .await;
+                let dialed = match dialed {
+                    Ok((mut ws_stream, response)) => {
+                        let subprotocol = response
+                            .headers()
+                            .get(SEC_WEBSOCKET_PROTOCOL)
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_string);
+                        if subprotocol.as_deref() == Some(codec.subprotocol().as_str()) {
+                            Ok((ws_stream, response, subprotocol))
+                        } else {
+                            // the server would misread every message
+                            let frame = CloseFrame {
+                                code: CloseCode::Protocol,
+                                reason: "codec not acknowledged".into(),
+                            };
+                            tokio::time::timeout(CLOSE_TIMEOUT, ws_stream.close(Some(frame)))
+                                .await
+                                .ok();
+                            Err(NetworkError::CodecMismatch {
+                                expected: codec.subprotocol(),
+                                acknowledged: subprotocol,
+                            })
+                        }
+                    }
+                    Err(e) => Err(NetworkError::handshake(e)),
+                };
+                match dialed {
+                    Ok((mut ws_stream, response, subprotocol)) => {
+                        if closing.load(Ordering::Acquire) {
+                            ws_stream.close(None).await.ok();
+                            break;
+                        }
+                        if let Some(ReconnectPolicy {
+                            queue_policy: OutageQueuePolicy::Drop,
+                            ..
+                        }) = reconnect
+                        {
+                            if attempt > 0 {
+                                // a close request must survive the drain
+                                let mut close = None;
+                                while let Ok(msg) = from_handler_rx.try_recv() {
+                                    if let Message::Close(frame) = msg {
+                                        close = Some(frame);
+                                    }
+                                }
+                                if let Some(frame) = close {
+                                    ws_stream.close(frame).await.ok();
+                                    break;
+                                }
+                            }
                         }
-                        Ok(tokio_tungstenite::tungstenite::Message::Binary(bts)) => {
-                            ev_tx
-                                .send(NetworkEvent::Message(
-                                    ConnectionHandle {
-                                        uuid: uuid::Uuid::nil(),
-                                    },
-                                    bts,
-                                ))
-                                .expect("failed to forward network message");
+                        attempt = 0;
+                        let info = Arc::new(ConnectionInfo {
+                            remote_addr: peer_addr(ws_stream.get_ref()),
+                            uri,
+                            subprotocol,
+                            headers: response.into_parts().0.headers,
+                            connected_at: SystemTime::now(),
+                            identity: None,
+                        });
+                        if ev_tx
+                            .send(NetworkEvent::Connected(handle.clone(), info.clone()))
+                            .is_err()
+                        {
+                            // the client was dropped
+                            break;
                         }
-                        Ok(m) => {
-                            warn!("unsupported message: {:?}", m);
+                        *current_info.lock().unwrap() = Some(info);
+                        let session_keepalive =
+                            keepalive.clone().map(|k| Arc::new(Keepalive::new(k)));
+                        *current_keepalive.lock().unwrap() = session_keepalive.clone();
+                        let reason = run_session(
+                            ws_stream,
+                            &handle,
+                            &ev_tx,
+                            &mut from_handler_rx,
+                            session_keepalive,
+                            &requests,
+                            &codec,
+                        )
+                        .await;
+                        requests.clear();
+                        current_keepalive.lock().unwrap().take();
+                        current_info.lock().unwrap().take();
+                        debug!("connection to {} ended: {:?}", s, reason);
+                        closed_by_server = matches!(
+                            reason,
+                            DisconnectReason::Closed {
+                                code: CloseCode::Normal | CloseCode::Policy | CloseCode::Away,
+                                ..
+                            }
+                        );
+                        if ev_tx
+                            .send(NetworkEvent::Disconnected(handle.clone(), reason))
+                            .is_err()
+                        {
+                            break;
                         }
                     }
-                })
-                .await;
-            };
+                    Err(e) => {
+                        warn!("failed to connect to {}: {}", s, e);
+                        if ev_tx
+                            .send(NetworkEvent::Error(Some(handle.clone()), e))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                }
 
-            let write_handle = async move {
-                loop {
-                    let req = from_handler_rx.try_recv();
-                    match req {
-                        Err(TryRecvError::Empty) => {
-                            // TODO: REPLACE SPINLOCK !
-                            continue
-                        },
-                        Err(e) => {
-                            warn!("failed to forward message to sink: {}", e);
-                        },
-                        Ok(ev) => {
-                            if let Err(e) = write
-                                .send(ev)
-                                .await {
-                                warn!("failed to send message to server: {}", e);
-                            }
-                        },
+                let policy = match &reconnect {
+                    Some(_) if closing.load(Ordering::Acquire) => break,
+                    Some(policy) if closed_by_server && !policy.reconnect_after_close => {
+                        debug!("not reconnecting to {}, the server closed the connection", s);
+                        break;
                     }
+                    Some(policy) => policy,
+                    None => break,
+                };
+                attempt += 1;
+                if matches!(policy.max_attempts, Some(max) if attempt > max) {
+                    warn!("giving up reconnecting to {} after {} attempts", s, attempt - 1);
+                    break;
                 }
-            };
-            join!(read_handle, write_handle);
+                tokio::select! {
+                    _ = tokio::time::sleep(policy.delay(attempt)) => {}
+                    _ = close_requested.notified() => {}
+                }
+                if closing.load(Ordering::Acquire) {
+                    break;
+                }
+                debug!("reconnecting to {} (attempt {})", s, attempt);
+                if ev_tx
+                    .send(NetworkEvent::Reconnecting(handle.clone(), attempt))
+                    .is_err()
+                {
+                    break;
+                }
+            }
         };
-        self.handle = Some(self.rt.spawn(event_loop));
+        self.handle = Some(self.rt.handle().spawn(event_loop));
         self.rx = Some(Arc::new(ev_rx));
-        self.tx = Some(Arc::new(from_handler_tx));
+        self.tx = Some(from_handler_tx);
+        Ok(())
+    }
+
+    /// Close the connection with the given close code and reason, without reconnecting.
+    pub fn close(&self, code: CloseCode, reason: &str) -> Result<(), NetworkError> {
+        self.closing.store(true, Ordering::Release);
+        // stored as a permit if the task isn't waiting yet
+        self.close_requested.notify_one();
+        self.send_raw_message(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.to_string().into(),
+        })))
     }
 
     pub fn try_recv(&self) -> Option<NetworkEvent> {
@@ -125,35 +445,316 @@ impl Client {
         }
     }
 
+    /// Wait for the next event, `None` when not connected or once the connection is over for
+    /// good.
+    pub async fn next_event(&self) -> Option<NetworkEvent> {
+        self.rx.as_ref()?.recv().await
+    }
+
+    /// Every event of the connection, ending with it.
+    pub fn events(&self) -> impl Stream<Item = NetworkEvent> + Unpin + '_ {
+        Box::pin(stream::unfold(self, |client| async move {
+            Some((client.next_event().await?, client))
+        }))
+    }
+
+    /// Wait for the next message of type `T`. Other messages and events received in the
+    /// meantime are discarded.
+    ///
+    /// Fails with `NetworkError::NotConnected` once the connection is over for good.
+    pub async fn recv<T: MessageType>(&self) -> Result<T, NetworkError> {
+        let mut parser = GenericParser::new();
+        parser.insert_type::<T>();
+        loop {
+            match self.next_event().await {
+                Some(NetworkEvent::Message(_, raw)) => {
+                    let enveloppe =
+                        Enveloppe::decode(self.codec(), raw).map_err(NetworkError::Decode)?;
+                    if enveloppe.message_type != T::message_type() {
+                        debug!("skipping {} message", enveloppe.message_type);
+                        continue;
+                    }
+                    return parser
+                        .parse_enveloppe(&enveloppe)
+                        .and_then(GenericParser::try_into_concrete_type)
+                        .map_err(NetworkError::Decode);
+                }
+                Some(ev) => debug!("skipping network event {:?}", ev),
+                None => return Err(NetworkError::NotConnected),
+            }
+        }
+    }
+
+    /// Same as [`send_message`](#method.send_message). The queue of the client is unbounded,
+    /// so this never waits.
+    pub async fn send<T: MessageType + Serialize>(&self, msg: &T) -> Result<(), NetworkError> {
+        let payload = self
+            .config
+            .codec
+            .encode_message(msg)
+            .map_err(NetworkError::Encode)?;
+        self.send_raw_message(self.config.frame_kind.frame(payload))
+    }
+
+    /// Send `req` with a fresh correlation id, and wait for the server to
+    /// [respond](crate::server::Server::respond) with a `Resp` or an `ErrorMessage`.
+    ///
+    /// Requests still pending when the connection drops fail with `NetworkError::NotConnected`,
+    /// the ones unanswered after [`ClientConfig::request_timeout`] with
+    /// `NetworkError::RequestTimeout`.
+    pub fn request<Req, Resp>(&self, req: &Req) -> Result<PendingResponse<Resp>, NetworkError>
+    where
+        Req: MessageType + Serialize,
+        Resp: MessageType,
+    {
+        let (id, rx) = self.requests.register();
+        let sent = self
+            .config
+            .codec
+            .encode_correlated(req, id)
+            .map_err(NetworkError::Encode)
+            .and_then(|payload| self.send_raw_message(self.config.frame_kind.frame(payload)));
+        if let Err(e) = sent {
+            self.requests.fail(id, NetworkError::NotConnected);
+            return Err(e);
+        }
+        let requests = self.requests.clone();
+        let timeout = self.config.request_timeout;
+        let timer = self.rt.handle().spawn(async move {
+            tokio::time::sleep(timeout).await;
+            requests.fail(id, NetworkError::RequestTimeout);
+        });
+        self.requests.set_timer(id, timer);
+        Ok(PendingResponse::new(id, rx))
+    }
+
     pub fn send_message<T: MessageType + Serialize + Clone>(
         &self,
         msg: &T,
-    ) {
-        let sev = SendEnveloppe {
-            message_type: T::message_type().to_string(),
-            payload: msg.clone(),
-        };
-        let payload =
-            tokio_tungstenite::tungstenite::Message::Binary(serde_json::to_vec(&sev).unwrap());
-        self.send_raw_message(payload)
+    ) -> Result<(), NetworkError> {
+        let payload = self
+            .config
+            .codec
+            .encode_message(msg)
+            .map_err(NetworkError::Encode)?;
+        self.send_raw_message(self.config.frame_kind.frame(payload))
     }
 
     pub fn send_raw_message(
         &self,
-        msg: tokio_tungstenite::tungstenite::Message,
-    ) {
-        if let Some(channel) = &self.tx {
-            if let Err(e) = channel.send(msg) {
-                warn!(
-                    "failed to forward message, sink: {:?}",
-                    e
-                );
-            }
+        msg: Message,
+    ) -> Result<(), NetworkError> {
+        match &self.tx {
+            Some(channel) => channel.send(msg).map_err(|_| {
+                NetworkError::SendOnClosedConnection(ConnectionHandle {
+                    uuid: uuid::Uuid::nil(),
+                })
+            }),
+            None => Err(NetworkError::NotConnected),
+        }
+    }
+
+}
+
+async fn run_session<S>(
+    ws_stream: tokio_tungstenite::WebSocketStream<S>,
+    handle: &ConnectionHandle,
+    ev_tx: &EventSender<NetworkEvent>,
+    from_handler_rx: &mut mpsc::UnboundedReceiver<Message>,
+    keepalive: Option<Arc<Keepalive>>,
+    requests: &PendingRequests,
+    codec: &Arc<dyn Codec>,
+) -> DisconnectReason
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut ticker = keepalive.as_ref().map(|k| k.interval());
+    let (mut write, mut read) = ws_stream.split();
+    // responses are handed to their pending request instead of being emitted, returns `false`
+    // once the client is gone
+    let forward = |raw: Vec<u8>| {
+        // only self-describing codecs carry correlation ids
+        let raw = if !codec.self_describing() {
+            Some(raw)
         } else {
-            warn!(
-                "trying to send message with an uninitialized client",
-            );
+            match Enveloppe::decode(codec.clone(), raw.clone()) {
+                Ok(enveloppe) => requests.resolve(enveloppe).map(|enveloppe| enveloppe.raw),
+                Err(_) => Some(raw),
+            }
+        };
+        match raw {
+            Some(raw) => ev_tx.send(NetworkEvent::Message(handle.clone(), raw)).is_ok(),
+            None => true,
         }
+    };
+    let read_handle = async {
+        let mut peer_close = None;
+        while let Some(msg) = read.next().await {
+            if let (Ok(msg), Some(keepalive)) = (&msg, &keepalive) {
+                keepalive.on_frame(msg);
+            }
+            match msg {
+                Err(e) => {
+                    error!("failed to receive message: {:?}", e);
+                    return Err(e);
+                }
+                Ok(Message::Binary(bts)) => {
+                    if !forward(bts) {
+                        break;
+                    }
+                }
+                Ok(Message::Text(text)) => {
+                    if !forward(text.into_bytes()) {
+                        break;
+                    }
+                }
+                Ok(Message::Close(frame)) => {
+                    peer_close = Some(frame);
+                }
+                // pings are answered by tungstenite itself
+                Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => {}
+            }
+        }
+        Ok(peer_close)
+    };
+
+    let write_handle = async {
+        loop {
+            tokio::select! {
+                req = from_handler_rx.recv() => match req {
+                    Some(Message::Close(frame)) => {
+                        if let Err(e) = write.send(Message::Close(frame.clone())).await {
+                            warn!("failed to send close frame to server: {}", e);
+                        }
+                        return Some(DisconnectReason::closed_locally(frame));
+                    }
+                    Some(ev) => {
+                        if let Err(e) = write.send(ev).await {
+                            warn!("failed to send message to server: {}", e);
+                            return None;
+                        }
+                    }
+                    None => {
+                        warn!("failed to forward message to sink: client dropped");
+                        return None;
+                    }
+                },
+                _ = Keepalive::next_tick(&mut ticker) => {
+                    let keepalive = keepalive.as_ref().expect("ticking without keepalive");
+                    match keepalive.tick() {
+                        Heartbeat::Wait => {}
+                        Heartbeat::Ping(payload) => {
+                            if let Err(e) = write.send(Message::Ping(payload)).await {
+                                warn!("failed to ping server: {}", e);
+                            }
+                        }
+                        Heartbeat::TimedOut => {
+                            debug!("connection to server timed out");
+                            let frame = CloseFrame {
+                                code: CloseCode::Away,
+                                reason: "keepalive timeout".into(),
+                            };
+                            tokio::time::timeout(
+                                CLOSE_TIMEOUT,
+                                write.send(Message::Close(Some(frame))),
+                            )
+                            .await
+                            .ok();
+                            return Some(DisconnectReason::Timeout);
+                        }
+                    }
+                }
+            }
+        }
+    };
+    let (local, res) = {
+        pin_mut!(read_handle, write_handle);
+        match select(read_handle, write_handle).await {
+            Either::Left((res, _)) => (None, res),
+            Either::Right((Some(DisconnectReason::Timeout), _)) => {
+                (Some(DisconnectReason::Timeout), Ok(None))
+            }
+            Either::Right((local, read_handle)) => {
+                // wait for the server to acknowledge the close frame
+                let res = tokio::time::timeout(CLOSE_TIMEOUT, read_handle)
+                    .await
+                    .unwrap_or(Ok(None));
+                (local, res)
+            }
+        }
+    };
+    if let Err(TungsteniteError::Capacity(e)) = &res {
+        let frame = CloseFrame {
+            code: CloseCode::Size,
+            reason: e.to_string().into(),
+        };
+        tokio::time::timeout(CLOSE_TIMEOUT, write.send(Message::Close(Some(frame))))
+            .await
+            .ok();
     }
+    match (local, res) {
+        (Some(reason), _) => reason,
+        (None, Ok(Some(frame))) => DisconnectReason::closed(frame),
+        (None, Ok(None)) => {
+            DisconnectReason::IoError("connection closed without a close frame".to_string())
+        }
+        (None, Err(e)) => {
+            let reason = DisconnectReason::from(&e);
+            ev_tx
+                .send(NetworkEvent::Error(Some(handle.clone()), e.into()))
+                .ok();
+            reason
+        }
+    }
+}
+
+fn peer_addr(stream: &MaybeTlsStream<TcpStream>) -> Option<SocketAddr> {
+    match stream {
+        MaybeTlsStream::Plain(socket) => socket.peer_addr().ok(),
+        #[cfg(feature = "tls")]
+        MaybeTlsStream::Rustls(stream) => stream.get_ref().0.peer_addr().ok(),
+        _ => None,
+    }
+}
+
+/// Handshake request asking the server to use `codec`.
+fn client_request(url: &url::Url, headers: &HeaderMap, protocols: &HeaderValue) -> Request {
+    let mut request = Request::get(url.as_str())
+        .body(())
+        .expect("URL was already parsed");
+    request.headers_mut().extend(headers.clone());
+    request
+        .headers_mut()
+        .insert(SEC_WEBSOCKET_PROTOCOL, protocols.clone());
+    request
+}
 
+#[cfg(feature = "tls")]
+async fn connect_tls(
+    url: &url::Url,
+    request: Request,
+    websocket: tokio_tungstenite::tungstenite::protocol::WebSocketConfig,
+    tls: Arc<tokio_rustls::rustls::ClientConfig>,
+) -> Result<
+    (
+        tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
+        tokio_tungstenite::tungstenite::handshake::client::Response,
+    ),
+    tokio_tungstenite::tungstenite::Error,
+> {
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let socket = tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+    tokio_tungstenite::client_async_tls_with_config(
+        request,
+        socket,
+        Some(websocket),
+        Some(tokio_tungstenite::Connector::Rustls(tls)),
+    )
+    .await
 }
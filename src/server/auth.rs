@@ -0,0 +1,63 @@
+use crate::shared::Identity;
+use std::fmt;
+use tokio_tungstenite::tungstenite::handshake::server::ErrorResponse;
+pub use tokio_tungstenite::tungstenite::{handshake::server::Request, http::StatusCode};
+
+/// HTTP response refusing a websocket upgrade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub status: StatusCode,
+    /// Body of the response.
+    pub reason: Option<String>,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            reason: None,
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED)
+    }
+
+    pub fn forbidden() -> Self {
+        Self::new(StatusCode::FORBIDDEN)
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    pub(crate) fn into_response(self) -> ErrorResponse {
+        let mut response = ErrorResponse::new(self.reason);
+        *response.status_mut() = self.status;
+        response
+    }
+}
+
+/// Decides whether a websocket upgrade is accepted, from its path, query string and headers.
+///
+/// Runs during the handshake, so a rejected client never reaches the application: its only
+/// trace is a `NetworkError::Rejected` event. Implemented by closures taking a `&Request`.
+pub trait Authenticator: Send + Sync + 'static {
+    fn authenticate(&self, request: &Request) -> Result<Identity, Rejection>;
+}
+
+impl<F> Authenticator for F
+where
+    F: Fn(&Request) -> Result<Identity, Rejection> + Send + Sync + 'static,
+{
+    fn authenticate(&self, request: &Request) -> Result<Identity, Rejection> {
+        self(request)
+    }
+}
+
+impl fmt::Debug for dyn Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Authenticator")
+    }
+}
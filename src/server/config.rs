@@ -0,0 +1,137 @@
+use crate::shared::{Codec, FrameKind, JsonCodec, KeepaliveConfig, MessageLimits};
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+use tokio_tungstenite::tungstenite::http::header::{HeaderName, ORIGIN, USER_AGENT};
+use super::{Authenticator, QueueConfig};
+#[cfg(feature = "tls")]
+use super::ServerTlsConfig;
+
+/// Settings of a [`Server`](super::Server), built by chaining its methods on
+/// `ServerConfig::default()`.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub(crate) bind: Option<String>,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<ServerTlsConfig>,
+    pub(crate) worker_threads: Option<NonZeroUsize>,
+    pub(crate) handshake_timeout: Option<Duration>,
+    pub(crate) keepalive: Option<KeepaliveConfig>,
+    pub(crate) queue: QueueConfig,
+    pub(crate) limits: MessageLimits,
+    pub(crate) frame_kind: FrameKind,
+    pub(crate) codecs: Vec<Arc<dyn Codec>>,
+    pub(crate) authenticator: Option<Arc<dyn Authenticator>>,
+    pub(crate) recorded_headers: Vec<HeaderName>,
+    pub(crate) report_errors: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            worker_threads: None,
+            handshake_timeout: None,
+            keepalive: None,
+            queue: QueueConfig::default(),
+            limits: MessageLimits::default(),
+            frame_kind: FrameKind::default(),
+            codecs: vec![Arc::new(JsonCodec)],
+            authenticator: None,
+            recorded_headers: vec![
+                USER_AGENT,
+                ORIGIN,
+                HeaderName::from_static("x-forwarded-for"),
+                HeaderName::from_static("x-real-ip"),
+            ],
+            report_errors: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Address listened on by [`Server::start`](super::Server::start). The Bevy plugin starts
+    /// listening on it at startup.
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.bind = Some(addr.into());
+        self
+    }
+
+    /// Serve `wss://` on the [`bind`](#method.bind) address.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: ServerTlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Number of threads of the tokio runtime, one per core by default. Ignored when the
+    /// runtime is shared.
+    pub fn worker_threads(mut self, threads: NonZeroUsize) -> Self {
+        self.worker_threads = Some(threads);
+        self
+    }
+
+    /// Drop connections that don't complete their TLS and websocket handshakes in time.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Ping every connection and drop the ones that stop answering.
+    pub fn keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Bound the outgoing queue of each connection.
+    pub fn queue(mut self, queue: QueueConfig) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Size limits of the messages received from clients.
+    pub fn limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Frame type of the messages sent to clients, binary by default.
+    pub fn frame_kind(mut self, frame_kind: FrameKind) -> Self {
+        self.frame_kind = frame_kind;
+        self
+    }
+
+    /// Codec of the connections that don't negotiate one, JSON by default. Replaces the codecs
+    /// added with [`add_codec`](#method.add_codec).
+    pub fn codec(mut self, codec: impl Codec) -> Self {
+        self.codecs = vec![Arc::new(codec)];
+        self
+    }
+
+    /// Also accept `codec` from clients requesting its [subprotocol](Codec::subprotocol).
+    pub fn add_codec(mut self, codec: impl Codec) -> Self {
+        self.codecs.push(Arc::new(codec));
+        self
+    }
+
+    /// Request headers kept in the [`ConnectionInfo`](crate::shared::ConnectionInfo) of each
+    /// connection. Defaults to `User-Agent`, `Origin`, `X-Forwarded-For` and `X-Real-IP`.
+    pub fn record_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.recorded_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Check every websocket upgrade request, and reject the ones that fail.
+    pub fn authenticate(mut self, authenticator: impl Authenticator) -> Self {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Let the Bevy plugin answer the messages it can't deliver with an
+    /// [`ErrorMessage`](crate::shared::ErrorMessage): malformed enveloppes, unregistered types
+    /// and invalid payloads. Off by default.
+    pub fn report_errors(mut self, enabled: bool) -> Self {
+        self.report_errors = enabled;
+        self
+    }
+}
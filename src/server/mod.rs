@@ -1,3 +1,15 @@
+mod auth;
+mod config;
+mod queue;
+mod rooms;
 mod ws_handler;
+#[cfg(feature = "tls")]
+mod tls;
 
+pub use auth::{Authenticator, Rejection, Request, StatusCode};
+pub use crate::shared::Identity;
+pub use config::ServerConfig;
+pub use queue::{OverflowPolicy, QueueConfig};
 pub use ws_handler::*;
+#[cfg(feature = "tls")]
+pub use tls::*;
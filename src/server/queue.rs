@@ -0,0 +1,239 @@
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::{Condvar, Mutex},
+};
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message;
+
+/// What to do with a message sent to a connection whose outgoing queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the message being sent.
+    DropNewest,
+    /// Discard the oldest queued message to make room.
+    DropOldest,
+    /// Close the connection with `DisconnectReason::SlowConsumer`.
+    Disconnect,
+    /// Block the sending thread until the connection catches up.
+    Block,
+}
+
+/// Capacity of the outgoing queue of each connection.
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// Maximum number of queued messages, unbounded if `None`.
+    pub capacity: Option<NonZeroUsize>,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: None,
+            overflow: OverflowPolicy::Disconnect,
+        }
+    }
+}
+
+pub(crate) enum PushError {
+    Closed,
+    /// The queue is full and its policy is `Disconnect`.
+    Overflow,
+}
+
+enum Pushed {
+    Done,
+    /// The queue is full and its policy is `Block`, the message is given back.
+    Full(Message),
+}
+
+struct State {
+    messages: VecDeque<Message>,
+    closed: bool,
+}
+
+/// Messages waiting to be written to a connection.
+pub(crate) struct OutgoingQueue {
+    config: QueueConfig,
+    state: Mutex<State>,
+    /// Wakes the session task when a message is pushed.
+    pushed: Notify,
+    /// Wakes blocked senders when a message is popped.
+    popped: Condvar,
+    /// Wakes blocked async senders when a message is popped.
+    space: Notify,
+}
+
+impl OutgoingQueue {
+    pub(crate) fn new(config: QueueConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State {
+                messages: VecDeque::new(),
+                closed: false,
+            }),
+            pushed: Notify::new(),
+            popped: Condvar::new(),
+            space: Notify::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+
+    fn try_push(&self, state: &mut State, msg: Message) -> Result<Pushed, PushError> {
+        if let Some(capacity) = self.config.capacity {
+            if !state.closed && state.messages.len() >= capacity.get() {
+                match self.config.overflow {
+                    OverflowPolicy::DropNewest => return Ok(Pushed::Done),
+                    OverflowPolicy::DropOldest => {
+                        state.messages.pop_front();
+                    }
+                    OverflowPolicy::Disconnect => return Err(PushError::Overflow),
+                    OverflowPolicy::Block => return Ok(Pushed::Full(msg)),
+                }
+            }
+        }
+        if state.closed {
+            return Err(PushError::Closed);
+        }
+        state.messages.push_back(msg);
+        self.pushed.notify_one();
+        Ok(Pushed::Done)
+    }
+
+    pub(crate) fn push(&self, mut msg: Message) -> Result<(), PushError> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            match self.try_push(&mut state, msg)? {
+                Pushed::Done => return Ok(()),
+                Pushed::Full(back) => msg = back,
+            }
+            state = self.popped.wait(state).unwrap();
+        }
+    }
+
+    /// Same as `push`, but a `Block` policy waits without blocking the thread.
+    pub(crate) async fn push_async(&self, mut msg: Message) -> Result<(), PushError> {
+        loop {
+            let space = self.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+            match self.try_push(&mut self.state.lock().unwrap(), msg)? {
+                Pushed::Done => return Ok(()),
+                Pushed::Full(back) => msg = back,
+            }
+            space.await;
+        }
+    }
+
+    /// Next message to write, `None` once the queue is closed and drained.
+    pub(crate) async fn pop(&self) -> Option<Message> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(msg) = state.messages.pop_front() {
+                    self.popped.notify_one();
+                    self.space.notify_waiters();
+                    return Some(msg);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            // a push between the check and here leaves a permit, so no wakeup is lost
+            self.pushed.notified().await;
+        }
+    }
+
+    /// Refuse new messages. Queued ones are still written unless `discard` is set.
+    pub(crate) fn close(&self, discard: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        if discard {
+            state.messages.clear();
+        }
+        self.pushed.notify_one();
+        self.popped.notify_all();
+        self.space.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread, time::Duration};
+
+    fn queue(capacity: usize, overflow: OverflowPolicy) -> OutgoingQueue {
+        OutgoingQueue::new(QueueConfig {
+            capacity: NonZeroUsize::new(capacity),
+            overflow,
+        })
+    }
+
+    async fn drain(queue: &OutgoingQueue) -> Vec<Message> {
+        queue.close(false);
+        let mut messages = Vec::new();
+        while let Some(msg) = queue.pop().await {
+            messages.push(msg);
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn drop_newest_discards_the_pushed_message() {
+        let queue = queue(2, OverflowPolicy::DropNewest);
+        for n in 1..=3 {
+            assert!(queue.push(Message::text(n.to_string())).is_ok());
+        }
+        assert_eq!(drain(&queue).await, vec![Message::text("1"), Message::text("2")]);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_makes_room() {
+        let queue = queue(2, OverflowPolicy::DropOldest);
+        for n in 1..=3 {
+            assert!(queue.push(Message::text(n.to_string())).is_ok());
+        }
+        assert_eq!(drain(&queue).await, vec![Message::text("2"), Message::text("3")]);
+    }
+
+    #[test]
+    fn disconnect_reports_an_overflow() {
+        let queue = queue(1, OverflowPolicy::Disconnect);
+        assert!(queue.push(Message::text("1")).is_ok());
+        assert!(matches!(queue.push(Message::text("2")), Err(PushError::Overflow)));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn block_waits_for_a_pop() {
+        let queue = Arc::new(queue(1, OverflowPolicy::Block));
+        assert!(queue.push(Message::text("1")).is_ok());
+        let sender = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(Message::text("2")).is_ok())
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!sender.is_finished());
+        assert_eq!(queue.pop().await, Some(Message::text("1")));
+        assert!(sender.join().unwrap());
+        assert_eq!(drain(&queue).await, vec![Message::text("2")]);
+    }
+
+    #[test]
+    fn close_wakes_blocked_senders() {
+        let queue = Arc::new(queue(1, OverflowPolicy::Block));
+        assert!(queue.push(Message::text("1")).is_ok());
+        let sender = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(Message::text("2")))
+        };
+        thread::sleep(Duration::from_millis(50));
+        queue.close(true);
+        assert!(matches!(sender.join().unwrap(), Err(PushError::Closed)));
+        assert_eq!(queue.len(), 0);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Named groups of connections, indexed both ways. A room exists while it has members.
+#[derive(Debug, Default)]
+pub(crate) struct Rooms {
+    members: HashMap<String, HashSet<Uuid>>,
+    joined: HashMap<Uuid, HashSet<String>>,
+}
+
+impl Rooms {
+    /// Returns `false` if `id` already was in `room`.
+    pub(crate) fn join(&mut self, id: Uuid, room: String) -> bool {
+        self.joined.entry(id).or_default().insert(room.clone());
+        self.members.entry(room).or_default().insert(id)
+    }
+
+    /// Returns `false` if `id` wasn't in `room`.
+    pub(crate) fn leave(&mut self, id: Uuid, room: &str) -> bool {
+        let left = match self.members.get_mut(room) {
+            Some(members) => {
+                let left = members.remove(&id);
+                if members.is_empty() {
+                    self.members.remove(room);
+                }
+                left
+            }
+            None => false,
+        };
+        if let Some(rooms) = self.joined.get_mut(&id) {
+            rooms.remove(room);
+            if rooms.is_empty() {
+                self.joined.remove(&id);
+            }
+        }
+        left
+    }
+
+    pub(crate) fn leave_all(&mut self, id: Uuid) {
+        for room in self.joined.remove(&id).unwrap_or_default() {
+            if let Some(members) = self.members.get_mut(&room) {
+                members.remove(&id);
+                if members.is_empty() {
+                    self.members.remove(&room);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn members(&self, room: &str) -> Option<&HashSet<Uuid>> {
+        self.members.get(room)
+    }
+
+    pub(crate) fn rooms_of(&self, id: Uuid) -> Option<&HashSet<String>> {
+        self.joined.get(&id)
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &String> {
+        self.members.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Both indexes hold the same memberships, and neither keeps empty sets.
+    fn assert_consistent(rooms: &Rooms) {
+        for (room, members) in &rooms.members {
+            assert!(!members.is_empty(), "room '{}' is empty", room);
+            for id in members {
+                assert!(rooms.joined[id].contains(room));
+            }
+        }
+        for (id, joined) in &rooms.joined {
+            assert!(!joined.is_empty(), "{} has no rooms", id);
+            for room in joined {
+                assert!(rooms.members[room].contains(id));
+            }
+        }
+    }
+
+    #[test]
+    fn join_and_leave() {
+        let mut rooms = Rooms::default();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        assert!(rooms.join(a, "red".to_string()));
+        assert!(!rooms.join(a, "red".to_string()));
+        assert!(rooms.join(b, "red".to_string()));
+        assert!(rooms.join(a, "blue".to_string()));
+        assert_consistent(&rooms);
+        assert_eq!(rooms.members("red").unwrap().len(), 2);
+        assert_eq!(rooms.rooms_of(a).unwrap().len(), 2);
+
+        assert!(rooms.leave(a, "red"));
+        assert!(!rooms.leave(a, "red"));
+        assert!(!rooms.leave(a, "green"));
+        assert_consistent(&rooms);
+        assert!(!rooms.members("red").unwrap().contains(&a));
+        assert_eq!(rooms.rooms_of(a).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn empty_rooms_are_removed() {
+        let mut rooms = Rooms::default();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        rooms.join(a, "red".to_string());
+        rooms.join(b, "red".to_string());
+        rooms.leave(a, "red");
+        rooms.leave(b, "red");
+        assert!(rooms.members("red").is_none());
+        assert!(rooms.rooms_of(a).is_none());
+        assert_eq!(rooms.names().count(), 0);
+        assert_consistent(&rooms);
+    }
+
+    #[test]
+    fn leave_all() {
+        let mut rooms = Rooms::default();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        rooms.join(a, "red".to_string());
+        rooms.join(a, "blue".to_string());
+        rooms.join(b, "blue".to_string());
+        rooms.leave_all(a);
+        assert_consistent(&rooms);
+        assert!(rooms.rooms_of(a).is_none());
+        assert!(rooms.members("red").is_none());
+        assert_eq!(rooms.names().collect::<Vec<_>>(), vec!["blue"]);
+        rooms.leave_all(a);
+        assert_consistent(&rooms);
+    }
+}
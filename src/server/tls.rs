@@ -0,0 +1,61 @@
+use super::ServerConfigError;
+use std::{fmt, fs, io::BufReader, path::Path, sync::Arc};
+use tokio_rustls::{
+    rustls::{
+        internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys},
+        Certificate, NoClientAuth, PrivateKey, ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+/// Certificate chain and private key used to serve `wss://` connections.
+#[derive(Clone)]
+pub struct ServerTlsConfig {
+    cert_chain: Vec<Certificate>,
+    private_key: PrivateKey,
+}
+
+impl fmt::Debug for ServerTlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerTlsConfig").finish_non_exhaustive()
+    }
+}
+
+impl ServerTlsConfig {
+    /// Load a PEM encoded certificate chain and private key (PKCS#8 or RSA) from memory.
+    pub fn from_pem(cert_chain: &[u8], private_key: &[u8]) -> Result<Self, ServerConfigError> {
+        let cert_chain = certs(&mut BufReader::new(cert_chain))
+            .map_err(|_| ServerConfigError::InvalidCertificate)?;
+        if cert_chain.is_empty() {
+            return Err(ServerConfigError::InvalidCertificate);
+        }
+        let mut keys = pkcs8_private_keys(&mut BufReader::new(private_key))
+            .map_err(|_| ServerConfigError::InvalidPrivateKey)?;
+        if keys.is_empty() {
+            keys = rsa_private_keys(&mut BufReader::new(private_key))
+                .map_err(|_| ServerConfigError::InvalidPrivateKey)?;
+        }
+        let private_key = keys
+            .into_iter()
+            .next()
+            .ok_or(ServerConfigError::InvalidPrivateKey)?;
+        Ok(Self {
+            cert_chain,
+            private_key,
+        })
+    }
+
+    /// Load a PEM encoded certificate chain and private key from files.
+    pub fn from_pem_files(
+        cert_chain: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> Result<Self, ServerConfigError> {
+        Self::from_pem(&fs::read(cert_chain)?, &fs::read(private_key)?)
+    }
+
+    pub(crate) fn acceptor(&self) -> Result<TlsAcceptor, ServerConfigError> {
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(self.cert_chain.clone(), self.private_key.clone())?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
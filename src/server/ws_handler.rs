@@ -1,35 +1,347 @@
-use crate::shared::{MessageType, SendEnveloppe};
-use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
-use futures::{join, pending};
-use futures_util::{future as ufuture, stream::TryStreamExt, SinkExt, StreamExt};
+use crate::shared::{Codec, MessageType};
+use super::rooms::Rooms;
+use futures::{
+    future::{select, Either},
+    pin_mut,
+    stream::{self, Stream},
+};
+use futures_util::{SinkExt, StreamExt};
 use log::{debug, trace, warn};
 use serde::Serialize;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    net::{SocketAddr, ToSocketAddrs},
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
 use uuid::Uuid;
 use thiserror::Error as TError;
 use tokio::{
-    net::{TcpListener, ToSocketAddrs},
-    runtime::Runtime,
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    runtime::Handle,
+    sync::oneshot,
     task::JoinHandle,
+    time::Instant,
+};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{Callback, Request, Response},
+    http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue},
+    protocol::{CloseFrame, WebSocketConfig},
+    Error as TungsteniteError,
+    Message,
 };
 use crate::shared::{
+    CloseCode,
+    ConnectionInfo,
+    DisconnectReason,
+    ErrorMessage,
+    FrameKind,
+    Heartbeat,
+    Keepalive,
+    KeepaliveConfig,
+    MessageLimits,
+    NetworkError,
+    with_handshake_deadline,
     NetworkEvent,
-    ConnectionHandle
+    ConnectionHandle,
+    RttStats,
+    SharedRuntime,
+    CLOSE_TIMEOUT,
+    event_channel,
+    EventReceiver,
+    EventSender,
 };
+use super::{
+    queue::{OutgoingQueue, PushError},
+    Identity,
+    QueueConfig,
+    ServerConfig,
+};
+#[cfg(feature = "tls")]
+use super::ServerTlsConfig;
 
 #[derive(TError, Debug)]
-pub enum ServerConfigError {}
+pub enum ServerConfigError {
+    #[error("failed to bind listener: {0}")]
+    Bind(#[source] std::io::Error),
+    #[error("failed to read TLS material: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("no bind address configured")]
+    MissingBindAddress,
+    #[cfg(feature = "tls")]
+    #[error("no valid PEM certificate found")]
+    InvalidCertificate,
+    #[cfg(feature = "tls")]
+    #[error("no valid PEM private key found")]
+    InvalidPrivateKey,
+    #[cfg(feature = "tls")]
+    #[error("invalid TLS configuration: {0}")]
+    Tls(#[from] tokio_rustls::rustls::TLSError),
+}
+
+#[derive(Clone)]
+enum Acceptor {
+    Plain,
+    #[cfg(feature = "tls")]
+    Tls(tokio_rustls::TlsAcceptor),
+}
+
+/// Pick the codec of a connection from the subprotocols offered by the client, in its order of
+/// preference. Falls back to the first codec if none of them is supported.
+fn negotiate_codec(
+    codecs: &[Arc<dyn Codec>],
+    request: &Request,
+    response: &mut Response,
+) -> Arc<dyn Codec> {
+    let offered = request
+        .headers()
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim);
+    for protocol in offered {
+        if let Some(codec) = codecs.iter().find(|c| c.subprotocol() == protocol) {
+            response.headers_mut().insert(
+                SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_str(protocol).expect("subprotocol read from a valid header"),
+            );
+            return codec.clone();
+        }
+    }
+    codecs[0].clone()
+}
+
+/// Everything a session task needs besides its stream.
+struct SessionContext {
+    handle: ConnectionHandle,
+    events: EventSender<NetworkEvent>,
+    queue: Arc<OutgoingQueue>,
+    close_rx: oneshot::Receiver<(CloseFrame<'static>, DisconnectReason)>,
+    keepalive: Option<Arc<Keepalive>>,
+    websocket: WebSocketConfig,
+    /// Shared by the TLS and websocket handshakes.
+    handshake_deadline: Option<Instant>,
+    /// Sent by the handshake callback.
+    info: oneshot::Receiver<Arc<ConnectionInfo>>,
+}
+
+async fn handle_session<S, C>(stream: S, ctx: SessionContext, negotiate: C) -> bool
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    C: Callback + Unpin,
+{
+    let SessionContext {
+        handle: client_handle,
+        events: ev_tx,
+        queue,
+        mut close_rx,
+        keepalive,
+        websocket,
+        handshake_deadline,
+        mut info,
+    } = ctx;
+    let handle_id = client_handle.id();
+    let handshake =
+        tokio_tungstenite::accept_hdr_async_with_config(stream, negotiate, Some(websocket));
+    let ws_stream = match with_handshake_deadline(handshake_deadline, handshake).await {
+        Ok(ws_stream) => ws_stream,
+        Err(TungsteniteError::Http(response)) => {
+            // refused by the authenticator, the connection was never registered
+            ev_tx
+                .send(NetworkEvent::Error(
+                    None,
+                    NetworkError::Rejected(response.status()),
+                ))
+                .ok();
+            return false;
+        }
+        Err(e) => {
+            warn!("websocket handshake with {:?} failed: {}", handle_id, e);
+            // like a refusal, the handle was never announced by a Connected event
+            ev_tx
+                .send(NetworkEvent::Error(None, NetworkError::handshake(e)))
+                .ok();
+            return false;
+        }
+    };
+    let info = info.try_recv().expect("the handshake callback sends the connection info");
+    if ev_tx
+        .send(NetworkEvent::Connected(client_handle.clone(), info))
+        .is_err()
+    {
+        // the server was dropped
+        return false;
+    }
+    let mut ticker = keepalive.as_ref().map(|k| k.interval());
+    let (mut outgoing, mut incoming) = ws_stream.split();
+    let handle_incoming = async {
+        let mut peer_close = None;
+        while let Some(msg) = incoming.next().await {
+            let msg = msg?;
+            if let Some(keepalive) = &keepalive {
+                keepalive.on_frame(&msg);
+            }
+            match msg {
+                Message::Binary(bts) => {
+                    if ev_tx
+                        .send(NetworkEvent::Message(client_handle.clone(), bts))
+                        .is_err()
+                    {
+                        // nobody listens anymore, end the session
+                        return Err(tokio_tungstenite::tungstenite::Error::ConnectionClosed);
+                    }
+                }
+                Message::Text(text) => {
+                    if ev_tx
+                        .send(NetworkEvent::Message(client_handle.clone(), text.into_bytes()))
+                        .is_err()
+                    {
+                        return Err(tokio_tungstenite::tungstenite::Error::ConnectionClosed);
+                    }
+                }
+                Message::Close(frame) => {
+                    peer_close = Some(frame);
+                }
+                // pings are answered by tungstenite itself
+                Message::Ping(_) | Message::Pong(_) => {}
+            }
+        }
+        Ok(peer_close)
+    };
+    let forward_handle = async {
+        loop {
+            tokio::select! {
+                msg = queue.pop() => match msg {
+                    Some(msg) => {
+                        if let Err(e) = outgoing.send(msg).await {
+                            warn!(
+                                "failed to send message to client {:?} : {}",
+                                handle_id, e
+                            );
+                        }
+                    }
+                    None => break,
+                },
+                _ = Keepalive::next_tick(&mut ticker) => {
+                    let keepalive = keepalive.as_ref().expect("ticking without keepalive");
+                    match keepalive.tick() {
+                        Heartbeat::Wait => {}
+                        Heartbeat::Ping(payload) => {
+                            if let Err(e) = outgoing.send(Message::Ping(payload)).await {
+                                warn!("failed to ping client {:?} : {}", handle_id, e);
+                            }
+                        }
+                        Heartbeat::TimedOut => {
+                            debug!("connection {:?} timed out", handle_id);
+                            let frame = CloseFrame {
+                                code: CloseCode::Away,
+                                reason: "keepalive timeout".into(),
+                            };
+                            tokio::time::timeout(
+                                CLOSE_TIMEOUT,
+                                outgoing.send(Message::Close(Some(frame))),
+                            )
+                            .await
+                            .ok();
+                            return Some(DisconnectReason::Timeout);
+                        }
+                    }
+                }
+            }
+        }
+        // the queue is closed once every message was sent, after the close frame when the
+        // connection is closed by the server
+        match close_rx.try_recv() {
+            Ok((frame, reason)) => {
+                debug!("closing connection {:?} : {}", handle_id, frame);
+                match tokio::time::timeout(CLOSE_TIMEOUT, outgoing.send(Message::Close(Some(frame))))
+                    .await
+                {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("failed to close connection {:?} : {}", handle_id, e),
+                    Err(_) => warn!("timed out closing connection {:?}", handle_id),
+                }
+                Some(reason)
+            }
+            Err(_) => None,
+        }
+    };
+    let (local, res) = {
+        pin_mut!(forward_handle, handle_incoming);
+        match select(forward_handle, handle_incoming).await {
+            Either::Left((Some(DisconnectReason::Timeout), _)) => {
+                (Some(DisconnectReason::Timeout), Ok(None))
+            }
+            Either::Left((local, handle_incoming)) => {
+                // wait for the peer to acknowledge the close frame
+                let res = tokio::time::timeout(CLOSE_TIMEOUT, handle_incoming)
+                    .await
+                    .unwrap_or(Ok(None));
+                (local, res)
+            }
+            Either::Right((res, _)) => (None, res),
+        }
+    };
+    if let Err(TungsteniteError::Capacity(e)) = &res {
+        let frame = CloseFrame {
+            code: CloseCode::Size,
+            reason: e.to_string().into(),
+        };
+        tokio::time::timeout(CLOSE_TIMEOUT, outgoing.send(Message::Close(Some(frame))))
+            .await
+            .ok();
+    }
+    let reason = match (local, res) {
+        (Some(reason), _) => reason,
+        (None, Ok(Some(frame))) => DisconnectReason::closed(frame),
+        (None, Ok(None)) => {
+            DisconnectReason::IoError("connection closed without a close frame".to_string())
+        }
+        (None, Err(e)) => {
+            warn!("failure in connection handling: {:?}", e);
+            let reason = DisconnectReason::from(&e);
+            ev_tx
+                .send(NetworkEvent::Error(Some(client_handle.clone()), e.into()))
+                .ok();
+            reason
+        }
+    };
+    debug!("connection {:?} ended: {:?}", handle_id, reason);
+    ev_tx
+        .send(NetworkEvent::Disconnected(client_handle.clone(), reason))
+        .ok();
+    true
+}
+
+/// How long a stopped server lets its sessions complete their close handshake.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Bookkeeping of a connection, kept from its accept until its `Disconnected` event is received.
+struct Session {
+    task: JoinHandle<()>,
+    queue: Arc<OutgoingQueue>,
+    /// Taken when the connection is being closed.
+    closer: Option<oneshot::Sender<(CloseFrame<'static>, DisconnectReason)>>,
+    keepalive: Option<Arc<Keepalive>>,
+    codec: Arc<dyn Codec>,
+    /// Set once the handshake completed.
+    info: Option<Arc<ConnectionInfo>>,
+}
 
 
 pub struct Server {
-    rt: Arc<Runtime>,
+    rt: SharedRuntime,
     server_handle: Option<JoinHandle<()>>,
-    sessions_events: Arc<Mutex<HashMap<Uuid, Arc<Receiver<NetworkEvent>>>>>,
-    sessions_sinks: Arc<Mutex<HashMap<Uuid, Arc<Sender<tokio_tungstenite::tungstenite::Message>>>>>,
-    sessions_handles: Arc<Mutex<HashMap<Uuid, JoinHandle<()>>>>,
+    /// Address of the listener, set while listening.
+    local_addr: Option<SocketAddr>,
+    sessions: Arc<Mutex<HashMap<Uuid, Session>>>,
+    /// Locked after `sessions` when both are needed.
+    rooms: Mutex<Rooms>,
+    /// Events of the listener and of every session.
+    events: (EventSender<NetworkEvent>, EventReceiver<NetworkEvent>),
+    config: ServerConfig,
 }
 
 impl Default for Server {
@@ -38,198 +350,553 @@ impl Default for Server {
     }
 }
 
+impl Drop for Server {
+    fn drop(&mut self) {
+        let closing = self.shutdown();
+        // an owned runtime goes away with the server, let the sessions send their close frames
+        // first, unless dropped from a task where blocking isn't allowed
+        if !closing.is_empty() && self.rt.is_owned() && Handle::try_current().is_err() {
+            self.rt.handle().block_on(futures::future::join_all(closing));
+        }
+    }
+}
+
 impl Server {
     pub fn new() -> Server {
+        Self::with_config(ServerConfig::default())
+    }
+
+    /// Server running on its own runtime, with [`ServerConfig::worker_threads`] threads.
+    pub fn with_config(config: ServerConfig) -> Server {
+        let rt = SharedRuntime::build(config.worker_threads);
+        Self::with_runtime(config, rt)
+    }
+
+    /// Server running on an existing runtime, e.g. the one of the application.
+    pub fn with_handle(config: ServerConfig, handle: Handle) -> Server {
+        Self::with_runtime(config, SharedRuntime::from_handle(handle))
+    }
+
+    /// Server running on a runtime shared with other servers and clients.
+    pub fn with_runtime(config: ServerConfig, rt: SharedRuntime) -> Server {
         Server {
-            rt: Arc::new(
-                tokio::runtime::Builder::new_multi_thread()
-                    .enable_all()
-                    .build()
-                    .expect("Could not build tokio runtime"),
-            ),
+            rt,
             server_handle: None,
-            sessions_events: Arc::new(Mutex::new(
-                HashMap::<Uuid, Arc<Receiver<NetworkEvent>>>::new(),
-            )),
-            sessions_sinks: Arc::new(Mutex::new(HashMap::<
-                Uuid,
-                Arc<Sender<tokio_tungstenite::tungstenite::Message>>,
-            >::new())),
-            sessions_handles: Arc::new(Mutex::new(HashMap::<Uuid, JoinHandle<()>>::new())),
+            local_addr: None,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            rooms: Mutex::new(Rooms::default()),
+            events: event_channel(),
+            config,
         }
     }
 
+    /// Settings of the server, including the changes made through its setters.
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
     pub fn is_running(&self) -> bool {
         self.server_handle.is_some()
     }
 
+    /// Address the server listens on, e.g. to find the port picked when binding port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Ping every connection and drop the ones that stop answering.
+    ///
+    /// Applies to connections accepted by the next call to [`listen`](#method.listen).
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) {
+        self.config.keepalive = keepalive;
+    }
+
+    /// Bound the outgoing queue of each connection, so a client that doesn't read its messages
+    /// can't exhaust the server memory.
+    ///
+    /// Applies to connections accepted by the next call to [`listen`](#method.listen).
+    pub fn set_queue_config(&mut self, queue: QueueConfig) {
+        self.config.queue = queue;
+    }
+
+    /// Size limits of the messages received from clients.
+    ///
+    /// Applies to connections accepted by the next call to [`listen`](#method.listen).
+    pub fn set_limits(&mut self, limits: MessageLimits) {
+        self.config.limits = limits;
+    }
+
+    /// Frame type used by [`send_message`](#method.send_message) and
+    /// [`broadcast`](#method.broadcast), binary by default.
+    pub fn set_frame_kind(&mut self, frame_kind: FrameKind) {
+        self.config.frame_kind = frame_kind;
+    }
+
+    /// Wire format of the messages, JSON by default. Replaces the codecs added with
+    /// [`add_codec`](#method.add_codec).
+    ///
+    /// Applies to connections accepted by the next call to [`listen`](#method.listen).
+    pub fn set_codec(&mut self, codec: impl Codec) {
+        self.config.codecs = vec![Arc::new(codec)];
+    }
+
+    /// Also accept `codec` from clients requesting its [subprotocol](Codec::subprotocol).
+    /// Connections that don't negotiate any codec keep using the one given to
+    /// [`set_codec`](#method.set_codec).
+    ///
+    /// Applies to connections accepted by the next call to [`listen`](#method.listen).
+    pub fn add_codec(&mut self, codec: impl Codec) {
+        self.config.codecs.push(Arc::new(codec));
+    }
+
+    /// Codec of the connections that don't negotiate one.
+    pub fn codec(&self) -> Arc<dyn Codec> {
+        self.config.codecs[0].clone()
+    }
+
+    /// Codec negotiated by a connection.
+    pub fn codec_for(&self, handle: &ConnectionHandle) -> Arc<dyn Codec> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&handle.id())
+            .map(|session| session.codec.clone())
+            .unwrap_or_else(|| self.codec())
+    }
+
+    /// Listen on the address given to [`ServerConfig::bind`], serving `wss://` if a
+    /// certificate was given to `ServerConfig::tls`.
+    pub fn start(&mut self) -> Result<(), ServerConfigError> {
+        let addr = self
+            .config
+            .bind
+            .clone()
+            .ok_or(ServerConfigError::MissingBindAddress)?;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = self.config.tls.clone() {
+            return self.listen_tls(addr, &tls);
+        }
+        self.listen(addr)
+    }
+
     pub fn listen(
         &mut self,
-        addr: impl ToSocketAddrs + Send + 'static,
+        addr: impl ToSocketAddrs,
     ) -> Result<(), ServerConfigError> {
-        self.start_listen_loop(addr)?;
+        self.start_listen_loop(addr, Acceptor::Plain)?;
         Ok(())
     }
 
+    /// Same as [`listen`](#method.listen), but serves `wss://` using the given certificate.
+    #[cfg(feature = "tls")]
+    pub fn listen_tls(
+        &mut self,
+        addr: impl ToSocketAddrs,
+        tls: &ServerTlsConfig,
+    ) -> Result<(), ServerConfigError> {
+        self.start_listen_loop(addr, Acceptor::Tls(tls.acceptor()?))?;
+        Ok(())
+    }
+
+    /// Stop accepting connections, and close the open ones with `CloseCode::Away`.
+    ///
+    /// Each connection then ends with a `Disconnected` event carrying
+    /// `DisconnectReason::Shutdown`, once the peer acknowledged the close frame or after a
+    /// short timeout. Queued messages are discarded, and pending handshakes aborted.
     pub fn stop(&mut self) {
+        self.shutdown();
+    }
+
+    /// Stop the server, returns the tasks closing the open connections.
+    fn shutdown(&mut self) -> Vec<JoinHandle<()>> {
         if let Some(conn) = self.server_handle.take() {
             debug!("stopping WS accept loop");
             conn.abort();
         }
-        for (k, conn) in self.sessions_handles.lock().unwrap().drain() {
-            debug!("aborting session {}", k);
-            conn.abort();
+        self.local_addr = None;
+        let mut closing = Vec::new();
+        let mut sessions = self.sessions.lock().unwrap();
+        for (k, mut session) in sessions.drain() {
+            if session.info.is_none() {
+                debug!("aborting session {}", k);
+                session.task.abort();
+                // the aborted task won't close it, wake up blocked senders here
+                session.queue.close(true);
+                continue;
+            }
+            debug!("closing session {}", k);
+            // a session already being closed keeps its own reason
+            if let Some(closer) = session.closer.take() {
+                let frame = CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "server shutting down".into(),
+                };
+                closer.send((frame, DisconnectReason::Shutdown)).ok();
+            }
+            session.queue.close(true);
+            let mut task = session.task;
+            closing.push(self.rt.handle().spawn(async move {
+                // the close handshake is bounded, but not a write stuck on a full socket
+                if tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut task).await.is_err() {
+                    task.abort();
+                }
+            }));
         }
+        *self.rooms.lock().unwrap() = Rooms::default();
+        closing
     }
 
+    /// Next event of the listener or of any connection, in the order they happened.
+    ///
+    /// A connection is forgotten once its `Disconnected` event has been returned.
     pub fn recv(&self) -> Option<NetworkEvent> {
-        let mut sel = crossbeam_channel::Select::new();
-        let mut ids = Vec::<Uuid>::new();
-        let mut receivers = Vec::new();
-        {
-            let chs = self.sessions_events.lock().unwrap();
-            for (i, rx) in chs.iter() {
-                ids.push(*i);
-                receivers.push(rx.clone());
-            }
-        }
-        if receivers.is_empty() {
-            return None;
-        }
-        for rx in receivers.iter() {
-            sel.recv(&**rx);
+        let ev = self.events.1.try_recv().ok()?;
+        Some(self.track(ev))
+    }
+
+    /// Wait for the next event, see [`recv`](#method.recv).
+    pub async fn next_event(&self) -> NetworkEvent {
+        let ev = self
+            .events
+            .1
+            .recv()
+            .await
+            .expect("the server holds a sender");
+        self.track(ev)
+    }
+
+    /// Every event of the listener and of the connections, see [`recv`](#method.recv).
+    pub fn events(&self) -> impl Stream<Item = NetworkEvent> + Unpin + '_ {
+        Box::pin(stream::unfold(self, |server| async move {
+            Some((server.next_event().await, server))
+        }))
+    }
+
+    fn track(&self, ev: NetworkEvent) -> NetworkEvent {
+        if let NetworkEvent::Disconnected(handle, _) = &ev {
+            debug!("connection closed for handle {}", handle.id());
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions.remove(&handle.id());
+            self.rooms.lock().unwrap().leave_all(handle.id());
         }
-        let mut r = None;
-        while r.is_none() {
-            let msg;
-            let index = sel.try_ready();
-            if index.is_err() {
-                return None;
-            }
-            {
-                let chs = self.sessions_events.lock().unwrap();
-                let res = chs.get(&ids[index.unwrap()]);
-                res?;
-                msg = Some(res.unwrap().recv());
-            }
-            let sess_id = ids[index.unwrap()];
-            r = match msg {
-                Some(Err(_e)) => {
-                    self.sessions_events.lock().unwrap().remove(&sess_id);
-                    self.sessions_handles.lock().unwrap().remove(&sess_id);
-                    debug!("connection closed for handle {}", sess_id);
-                    None
-                }
-                Some(Ok(m)) => match m {
-                    NetworkEvent::Error(_, _) => {
-                        self.sessions_events.lock().unwrap().remove(&sess_id);
-                        self.sessions_handles.lock().unwrap().remove(&sess_id);
-                        Some(m)
-                    }
-                    _ => Some(m),
-                },
-                None => {
-                    panic!("none message, this should never happens.")
-                }
-            };
+        ev
+    }
+
+    /// Smoothed round-trip time of a connection, available once keepalive is enabled and the
+    /// first Pong was received.
+    pub fn rtt(&self, handle: &ConnectionHandle) -> Option<RttStats> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&handle.id())
+            .and_then(|session| session.keepalive.as_ref()?.rtt())
+    }
+
+    /// Round-trip times of every connection with at least one measurement.
+    pub fn rtts(&self) -> Vec<(ConnectionHandle, RttStats)> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, session)| session.info.is_some())
+            .filter_map(|(id, session)| {
+                Some((ConnectionHandle { uuid: *id }, session.keepalive.as_ref()?.rtt()?))
+            })
+            .collect()
+    }
+
+    /// Close a single connection with the given close code and reason.
+    ///
+    /// The session is torn down once the peer acknowledged the close frame, or after a short
+    /// timeout. No message can be sent to `handle` once this returns.
+    pub fn disconnect(
+        &self,
+        handle: &ConnectionHandle,
+        code: CloseCode,
+        reason: &str,
+    ) -> Result<(), NetworkError> {
+        let frame = CloseFrame {
+            code,
+            reason: reason.to_string().into(),
+        };
+        let reason = DisconnectReason::kicked(Some(frame.clone()));
+        self.close_session(handle, frame, reason, false)
+    }
+
+    fn close_session(
+        &self,
+        handle: &ConnectionHandle,
+        frame: CloseFrame<'static>,
+        reason: DisconnectReason,
+        discard_queued: bool,
+    ) -> Result<(), NetworkError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(&handle.id())
+            .ok_or_else(|| NetworkError::UnknownHandle(handle.clone()))?;
+        let closer = session
+            .closer
+            .take()
+            .ok_or_else(|| NetworkError::SendOnClosedConnection(handle.clone()))?;
+        // the close frame must reach the session before its queue is closed
+        let sent = closer.send((frame, reason));
+        session.queue.close(discard_queued);
+        sent.map_err(|_| NetworkError::SendOnClosedConnection(handle.clone()))
+    }
+
+    /// Identity given by the [authenticator](ServerConfig::authenticate) to a connection.
+    pub fn identity(&self, handle: &ConnectionHandle) -> Option<Identity> {
+        self.connection_info(handle)?.identity.clone()
+    }
+
+    /// Peer address, request and identity of a connection, available once it is connected.
+    pub fn connection_info(&self, handle: &ConnectionHandle) -> Option<Arc<ConnectionInfo>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&handle.id())
+            .and_then(|session| session.info.clone())
+    }
+
+    /// Number of messages waiting to be written to a connection.
+    pub fn queue_depth(&self, handle: &ConnectionHandle) -> Option<usize> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&handle.id())
+            .filter(|session| session.info.is_some())
+            .map(|session| session.queue.len())
+    }
+
+    /// Add a connection to `room`, creating it if needed. A connection can be in any number
+    /// of rooms, and leaves them all when it disconnects.
+    pub fn join(
+        &self,
+        handle: &ConnectionHandle,
+        room: impl Into<String>,
+    ) -> Result<(), NetworkError> {
+        let sessions = self.sessions.lock().unwrap();
+        if !matches!(sessions.get(&handle.id()), Some(session) if session.info.is_some()) {
+            return Err(NetworkError::UnknownHandle(handle.clone()));
         }
-        r
+        self.rooms.lock().unwrap().join(handle.id(), room.into());
+        Ok(())
+    }
+
+    /// Remove a connection from `room`, returns `false` if it wasn't a member. Empty rooms are
+    /// deleted.
+    pub fn leave(&self, handle: &ConnectionHandle, room: &str) -> bool {
+        self.rooms.lock().unwrap().leave(handle.id(), room)
+    }
+
+    /// Remove a connection from every room it joined.
+    pub fn leave_all(&self, handle: &ConnectionHandle) {
+        self.rooms.lock().unwrap().leave_all(handle.id());
+    }
+
+    pub fn is_member(&self, handle: &ConnectionHandle, room: &str) -> bool {
+        self.rooms
+            .lock()
+            .unwrap()
+            .members(room)
+            .is_some_and(|members| members.contains(&handle.id()))
+    }
+
+    /// Connections currently in `room`.
+    pub fn room_members(&self, room: &str) -> Vec<ConnectionHandle> {
+        self.rooms
+            .lock()
+            .unwrap()
+            .members(room)
+            .into_iter()
+            .flatten()
+            .map(|uuid| ConnectionHandle { uuid: *uuid })
+            .collect()
+    }
+
+    /// Rooms joined by a connection.
+    pub fn rooms_of(&self, handle: &ConnectionHandle) -> Vec<String> {
+        self.rooms
+            .lock()
+            .unwrap()
+            .rooms_of(handle.id())
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Names of the rooms with at least one member.
+    pub fn rooms(&self) -> Vec<String> {
+        self.rooms.lock().unwrap().names().cloned().collect()
     }
 
     fn start_listen_loop(
         &mut self,
-        addr: impl ToSocketAddrs + Send + 'static,
+        addr: impl ToSocketAddrs,
+        acceptor: Acceptor,
     ) -> Result<(), ServerConfigError> {
-        let rt = self.rt.clone();
-        let sessions_events = self.sessions_events.clone();
-        let sessions_handles = self.sessions_handles.clone();
-        let sessions_sinks = self.sessions_sinks.clone();
+        // a handle only, so the runtime is never dropped from one of its own tasks
+        let rt = self.rt.handle().clone();
+        let sessions = self.sessions.clone();
+        let events = self.events.0.clone();
+        let keepalive = self.config.keepalive.clone();
+        let queue_config = self.config.queue.clone();
+        let websocket = self.config.limits.websocket_config();
+        let handshake_timeout = self.config.handshake_timeout;
+        let codecs = Arc::new(self.config.codecs.clone());
+        let authenticator = self.config.authenticator.clone();
+        let recorded_headers = Arc::new(self.config.recorded_headers.clone());
+        // bound synchronously, so listening also works from a task of the runtime
+        let listener = std::net::TcpListener::bind(addr)
+            .and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                let _rt = self.rt.handle().enter();
+                TcpListener::from_std(listener)
+            })
+            .map_err(ServerConfigError::Bind)?;
+        self.local_addr = listener.local_addr().ok();
 
         let listen_loop = async move {
-            let try_socket = TcpListener::bind(addr).await;
-            let listener = try_socket.expect("Failed to bind");
-            while let Ok((socket, addr)) = listener.accept().await {
+            loop {
+                let (socket, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("failed to accept connection: {}", e);
+                        events
+                            .send(NetworkEvent::Error(None, NetworkError::Io(e)))
+                            .ok();
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
                 debug!("new connection from {:?}", addr);
+                let handshake_deadline = handshake_timeout.map(|limit| Instant::now() + limit);
                 let client_handle = ConnectionHandle::new();
                 let handle_id = client_handle.id();
-                let (ev_tx, ev_rx) = unbounded();
-                let (from_handler_tx, from_handler_rx) = unbounded();
-
-                let receiver = Arc::new(ev_rx);
-                let sender = Arc::new(from_handler_tx);
+                let ev_tx = events.clone();
+                let queue = Arc::new(OutgoingQueue::new(queue_config.clone()));
+                let (close_tx, close_rx) = oneshot::channel();
+                let (info_tx, info_rx) = oneshot::channel();
 
-                let handle = async move {
-                    let ws_stream = tokio_tungstenite::accept_async(socket)
-                        .await
-                        .expect("Error during the websocket handshake occurred");
-                    ev_tx
-                        .send(NetworkEvent::Connected(client_handle.clone()))
-                        .expect("failed to send network event");
-                    let (mut outgoing, incoming) = ws_stream.split();
-                    let handle_incoming = incoming.try_for_each(|msg| {
-                        match msg {
-                            tokio_tungstenite::tungstenite::Message::Binary(bts) => {
-                                ev_tx
-                                    .send(NetworkEvent::Message(client_handle.clone(), bts))
-                                    .expect("failed to send network event");
-                            }
-                            tokio_tungstenite::tungstenite::Message::Close(_) => {
-                                ev_tx
-                                    .send(NetworkEvent::Disconnected(client_handle.clone()))
-                                    .expect("failed to send network event");
-                            }
-                            _ => {
-                                warn!("unsupported format for message: {:?}", msg);
-                            }
-                        }
-
-                        ufuture::ok(())
-                    });
-                    let forward_handle = async move {
-                        loop {
-                            let req = from_handler_rx.try_recv();
-                            match req {
-                                Err(TryRecvError::Empty) => {
-                                    pending!()
-                                }
-                                Err(e) => {
-                                    warn!(
-                                        "failed to forward message to client sink {:?} : {}",
-                                        handle_id, e
+                let acceptor = acceptor.clone();
+                let session_keepalive = keepalive.clone().map(|k| Arc::new(Keepalive::new(k)));
+                let keepalive = session_keepalive.clone();
+                let negotiate = {
+                    let codecs = codecs.clone();
+                    let sessions = sessions.clone();
+                    let authenticator = authenticator.clone();
+                    let recorded_headers = recorded_headers.clone();
+                    // the signature is imposed by tungstenite
+                    #[allow(clippy::result_large_err)]
+                    move |request: &Request, mut response: Response| {
+                        let identity = match &authenticator {
+                            Some(authenticator) => match authenticator.authenticate(request) {
+                                Ok(identity) => Some(identity),
+                                Err(rejection) => {
+                                    debug!(
+                                        "rejecting connection {:?} from {:?} : {}",
+                                        handle_id, addr, rejection.status
                                     );
+                                    return Err(rejection.into_response());
                                 }
-                                Ok(ev) => {
-                                    if let Err(e) = outgoing.send(ev).await {
-                                        warn!(
-                                            "failed to send message to client {:?} : {}",
-                                            handle_id, e
-                                        );
-                                    }
-                                }
-                            }
+                            },
+                            None => None,
+                        };
+                        let codec = negotiate_codec(&codecs, request, &mut response);
+                        debug!("connection {:?} uses the {} codec", handle_id, codec.name());
+                        let info = Arc::new(ConnectionInfo {
+                            remote_addr: Some(addr),
+                            uri: request.uri().clone(),
+                            headers: recorded_headers
+                                .iter()
+                                .flat_map(|name| {
+                                    request
+                                        .headers()
+                                        .get_all(name)
+                                        .iter()
+                                        .map(move |value| (name.clone(), value.clone()))
+                                })
+                                .collect(),
+                            subprotocol: response
+                                .headers()
+                                .get(SEC_WEBSOCKET_PROTOCOL)
+                                .and_then(|value| value.to_str().ok())
+                                .map(str::to_string),
+                            connected_at: SystemTime::now(),
+                            identity,
+                        });
+                        if let Some(session) = sessions.lock().unwrap().get_mut(&handle_id) {
+                            session.codec = codec;
+                            session.info = Some(info.clone());
                         }
+                        info_tx.send(info).ok();
+                        Ok(response)
+                    }
+                };
+                let sessions_cleanup = sessions.clone();
+                let session_queue = queue.clone();
+                let ctx = SessionContext {
+                    handle: client_handle,
+                    events: ev_tx,
+                    queue: queue.clone(),
+                    close_rx,
+                    keepalive,
+                    websocket,
+                    handshake_deadline,
+                    info: info_rx,
+                };
+                let handle = async move {
+                    let connected = match acceptor {
+                        Acceptor::Plain => handle_session(socket, ctx, negotiate).await,
+                        #[cfg(feature = "tls")]
+                        Acceptor::Tls(tls) => match with_handshake_deadline(
+                            handshake_deadline,
+                            tls.accept(socket),
+                        )
+                        .await
+                        {
+                            Ok(stream) => handle_session(stream, ctx, negotiate).await,
+                            Err(e) => {
+                                warn!("TLS handshake with {:?} failed: {}", addr, e);
+                                ctx.events
+                                    .send(NetworkEvent::Error(
+                                        None,
+                                        NetworkError::Handshake(Box::new(e.into())),
+                                    ))
+                                    .ok();
+                                false
+                            }
+                        },
                     };
-                    if let (_, Err(e)) = join!(forward_handle, handle_incoming) {
-                        warn!("failure in connection handling: {:?}", e);
+                    // wake up blocked senders, and refuse new messages
+                    session_queue.close(true);
+                    // no Disconnected event will ever remove it
+                    if !connected {
+                        sessions_cleanup.lock().unwrap().remove(&handle_id);
                     }
                 };
 
-                let session_handle = rt.spawn(handle);
-                sessions_handles
-                    .lock()
-                    .unwrap()
-                    .insert(handle_id, session_handle);
-                sessions_events
-                    .lock()
-                    .unwrap()
-                    .insert(handle_id, receiver.clone());
-                sessions_sinks.lock().unwrap().insert(handle_id, sender);
+                // hold the lock so the session can't look itself up before it is registered
+                let mut registered = sessions.lock().unwrap();
+                let task = rt.spawn(handle);
+                registered.insert(
+                    handle_id,
+                    Session {
+                        task,
+                        queue,
+                        closer: Some(close_tx),
+                        keepalive: session_keepalive,
+                        codec: codecs[0].clone(),
+                        info: None,
+                    },
+                );
             }
         };
 
         trace!("WS server started listening");
 
-        self.server_handle = Some(self.rt.spawn(listen_loop));
+        self.server_handle = Some(self.rt.handle().spawn(listen_loop));
 
         Ok(())
     }
@@ -238,55 +905,180 @@ impl Server {
         &self,
         handle: &ConnectionHandle,
         msg: &T,
-    ) {
-        let sev = SendEnveloppe {
-            message_type: T::message_type().to_string(),
-            payload: msg.clone(),
-        };
-        let payload =
-            tokio_tungstenite::tungstenite::Message::Binary(serde_json::to_vec(&sev).unwrap());
-        self.send_raw_message(handle, payload)
+    ) -> Result<(), NetworkError> {
+        let payload = self
+            .codec_for(handle)
+            .encode_message(msg)
+            .map_err(NetworkError::Encode)?;
+        self.send_raw_message(handle, self.config.frame_kind.frame(payload))
     }
 
     pub fn send_raw_message(
         &self,
         handle: &ConnectionHandle,
-        msg: tokio_tungstenite::tungstenite::Message,
-    ) {
-        let client;
-        {
-            let map = self.sessions_sinks.lock().unwrap();
-            client = map.get(&handle.id()).cloned();
+        msg: Message,
+    ) -> Result<(), NetworkError> {
+        let pushed = self.queue(handle)?.push(msg);
+        self.on_pushed(handle, pushed)
+    }
+
+    /// Same as [`send_message`](#method.send_message), but waits for room in the queue of
+    /// `handle` without blocking the thread when its overflow policy is `Block`.
+    pub async fn send<T: MessageType + Serialize>(
+        &self,
+        handle: &ConnectionHandle,
+        msg: &T,
+    ) -> Result<(), NetworkError> {
+        let payload = self
+            .codec_for(handle)
+            .encode_message(msg)
+            .map_err(NetworkError::Encode)?;
+        let msg = self.config.frame_kind.frame(payload);
+        let pushed = self.queue(handle)?.push_async(msg).await;
+        self.on_pushed(handle, pushed)
+    }
+
+    /// Answer the request `req_id` received from `handle`, with a response or an
+    /// [`ErrorMessage`](crate::shared::ErrorMessage). The id is the `correlation_id` of the
+    /// request enveloppe.
+    pub fn respond<T: MessageType + Serialize>(
+        &self,
+        handle: &ConnectionHandle,
+        req_id: u64,
+        resp: &T,
+    ) -> Result<(), NetworkError> {
+        let payload = self
+            .codec_for(handle)
+            .encode_correlated(resp, req_id)
+            .map_err(NetworkError::Encode)?;
+        self.send_raw_message(handle, self.config.frame_kind.frame(payload))
+    }
+
+    /// Send an `ErrorMessage` to `handle`, answering the request `correlation_id` if set.
+    pub fn send_error(
+        &self,
+        handle: &ConnectionHandle,
+        correlation_id: Option<u64>,
+        error: &ErrorMessage,
+    ) -> Result<(), NetworkError> {
+        match correlation_id {
+            Some(id) => self.respond(handle, id, error),
+            None => self.send_message(handle, error),
         }
-        if let Some(channel) = client {
-            if let Err(e) = channel.send(msg) {
-                warn!(
-                    "failed to forward message to client {:?} sink: {:?}",
-                    handle, e
-                );
+    }
+
+    fn queue(&self, handle: &ConnectionHandle) -> Result<Arc<OutgoingQueue>, NetworkError> {
+        match self.sessions.lock().unwrap().get(&handle.id()) {
+            Some(session) => Ok(session.queue.clone()),
+            None => Err(NetworkError::UnknownHandle(handle.clone())),
+        }
+    }
+
+    fn on_pushed(
+        &self,
+        handle: &ConnectionHandle,
+        pushed: Result<(), PushError>,
+    ) -> Result<(), NetworkError> {
+        match pushed {
+            Ok(()) => Ok(()),
+            Err(PushError::Closed) => Err(NetworkError::SendOnClosedConnection(handle.clone())),
+            Err(PushError::Overflow) => {
+                warn!("dropping slow connection {:?}", handle);
+                let frame = CloseFrame {
+                    code: CloseCode::Again,
+                    reason: "slow consumer".into(),
+                };
+                self.close_session(handle, frame, DisconnectReason::SlowConsumer, true)
+                    .ok();
+                Err(NetworkError::QueueFull(handle.clone()))
             }
-        } else {
-            warn!(
-                "trying to send to a non existing client handle {:?}",
-                handle
-            );
         }
     }
 
     pub fn broadcast<T: MessageType + Serialize + Clone>(&self, msg: T) {
-        let sev = SendEnveloppe {
-            message_type: T::message_type().to_string(),
-            payload: msg,
-        };
-        let payload =
-            tokio_tungstenite::tungstenite::Message::Binary(serde_json::to_vec(&sev).unwrap());
-        let clients;
-        {
-            let map = self.sessions_sinks.lock().unwrap();
-            clients = map.keys().cloned().collect::<Vec<Uuid>>();
-        }
-        for c in clients {
-            self.send_raw_message(&ConnectionHandle { uuid: c }, payload.clone());
+        self.broadcast_where(&msg, |_| true);
+    }
+
+    /// Send `msg` to each of `handles`.
+    pub fn broadcast_to<T: MessageType + Serialize + Clone>(
+        &self,
+        handles: &[ConnectionHandle],
+        msg: T,
+    ) {
+        let ids = handles.iter().map(ConnectionHandle::id).collect::<HashSet<_>>();
+        self.broadcast_where(&msg, |id| ids.contains(id));
+    }
+
+    /// Send `msg` to every connection but `except`, usually the one it originates from.
+    pub fn broadcast_except<T: MessageType + Serialize + Clone>(
+        &self,
+        except: &ConnectionHandle,
+        msg: T,
+    ) {
+        self.broadcast_where(&msg, |id| *id != except.id());
+    }
+
+    /// Send `msg` to every member of `room`.
+    pub fn broadcast_to_room<T: MessageType + Serialize + Clone>(&self, room: &str, msg: T) {
+        let members = self.room_members_set(room);
+        self.broadcast_where(&msg, |id| members.contains(id));
+    }
+
+    /// Send `msg` to every member of `room` but `except`.
+    pub fn broadcast_to_room_except<T: MessageType + Serialize + Clone>(
+        &self,
+        room: &str,
+        except: &ConnectionHandle,
+        msg: T,
+    ) {
+        let members = self.room_members_set(room);
+        self.broadcast_where(&msg, |id| *id != except.id() && members.contains(id));
+    }
+
+    fn room_members_set(&self, room: &str) -> HashSet<Uuid> {
+        self.rooms
+            .lock()
+            .unwrap()
+            .members(room)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn broadcast_where<T: MessageType + Serialize>(
+        &self,
+        msg: &T,
+        include: impl Fn(&Uuid) -> bool,
+    ) {
+        let clients = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, session)| {
+                session.info.is_some() && session.closer.is_some() && include(id)
+            })
+            .map(|(id, session)| (*id, session.codec.clone()))
+            .collect::<Vec<_>>();
+        // encode once per codec in use
+        let mut payloads = HashMap::<&'static str, Message>::new();
+        for (c, codec) in clients {
+            let handle = ConnectionHandle { uuid: c };
+            let payload = match payloads.get(codec.name()) {
+                Some(payload) => payload.clone(),
+                None => match codec.encode_message(msg) {
+                    Ok(payload) => payloads
+                        .entry(codec.name())
+                        .or_insert_with(|| self.config.frame_kind.frame(payload))
+                        .clone(),
+                    Err(e) => {
+                        warn!("failed to encode broadcast message: {}", e);
+                        continue;
+                    }
+                },
+            };
+            if let Err(e) = self.send_raw_message(&handle, payload) {
+                warn!("failed to broadcast message: {}", e);
+            }
         }
     }
 }
@@ -0,0 +1,75 @@
+use crossbeam_channel::{unbounded, Receiver, SendError, Sender, TryRecvError};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Unbounded queue of events, polled with `try_recv` or awaited with `recv`.
+pub(crate) fn event_channel<T>() -> (EventSender<T>, EventReceiver<T>) {
+    let (tx, rx) = unbounded();
+    let notify = Arc::new(Notify::new());
+    (
+        EventSender {
+            tx,
+            waker: Waker(notify.clone()),
+        },
+        EventReceiver { rx, notify },
+    )
+}
+
+/// Wakes up the tasks awaiting events when dropped, so they notice the last sender is gone.
+#[derive(Clone)]
+struct Waker(Arc<Notify>);
+
+impl Drop for Waker {
+    fn drop(&mut self) {
+        self.0.notify_waiters();
+    }
+}
+
+pub(crate) struct EventSender<T> {
+    tx: Sender<T>,
+    // dropped after `tx`
+    waker: Waker,
+}
+
+impl<T> Clone for EventSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+impl<T> EventSender<T> {
+    pub(crate) fn send(&self, ev: T) -> Result<(), SendError<T>> {
+        self.tx.send(ev)?;
+        self.waker.0.notify_waiters();
+        Ok(())
+    }
+}
+
+pub(crate) struct EventReceiver<T> {
+    rx: Receiver<T>,
+    notify: Arc<Notify>,
+}
+
+impl<T> EventReceiver<T> {
+    pub(crate) fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// Next event, `None` once every sender is dropped and the queue is drained.
+    pub(crate) async fn recv(&self) -> Option<T> {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            // registered before looking at the queue, so no send is missed
+            notified.as_mut().enable();
+            match self.rx.try_recv() {
+                Ok(ev) => return Some(ev),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => notified.await,
+            }
+        }
+    }
+}
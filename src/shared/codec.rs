@@ -0,0 +1,393 @@
+use super::{MessageType, SendEnveloppe};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+
+/// Wire format of the messages exchanged over a connection.
+///
+/// Every message is encoded as a `{ "t": <message type>, "d": <payload> }` object in the format
+/// of the codec, plus an `"i"` correlation id for requests and their responses. Payloads are
+/// decoded lazily, once their type tag has been looked up.
+pub trait Codec: Send + Sync + 'static {
+    /// Short identifier of the format, e.g. `json`.
+    fn name(&self) -> &'static str;
+
+    /// Websocket subprotocol used to negotiate this codec during the handshake.
+    fn subprotocol(&self) -> String {
+        format!("bwa.{}.v1", self.name())
+    }
+
+    /// Whether the format can omit fields, which is required to carry correlation ids.
+    fn self_describing(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> anyhow::Result<Vec<u8>>;
+
+    /// Call `visit` with a deserializer reading `bytes`.
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+    ) -> anyhow::Result<()>;
+}
+
+impl dyn Codec {
+    /// Encode `msg` in its enveloppe.
+    pub fn encode_message<T: MessageType + Serialize>(&self, msg: &T) -> anyhow::Result<Vec<u8>> {
+        self.encode(&SendEnveloppe {
+            message_type: T::message_type().to_string(),
+            payload: msg,
+            correlation_id: None,
+        })
+    }
+
+    /// Encode `msg` in an enveloppe carrying the correlation id `id`.
+    pub fn encode_correlated<T: MessageType + Serialize>(
+        &self,
+        msg: &T,
+        id: u64,
+    ) -> anyhow::Result<Vec<u8>> {
+        if !self.self_describing() {
+            anyhow::bail!("{} codec cannot carry correlation ids", self.name());
+        }
+        self.encode(&SendEnveloppe {
+            message_type: T::message_type().to_string(),
+            payload: msg,
+            correlation_id: Some(id),
+        })
+    }
+
+    pub fn decode_as<T: DeserializeOwned>(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        let mut value = None;
+        self.decode(bytes, &mut |de| {
+            value = Some(erased_serde::deserialize::<T>(de)?);
+            Ok(())
+        })?;
+        value.ok_or_else(|| anyhow::anyhow!("{} codec did not decode anything", self.name()))
+    }
+}
+
+impl fmt::Debug for dyn Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Codec({})", self.name())
+    }
+}
+
+/// Human readable JSON, the default codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+    ) -> anyhow::Result<()> {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))?;
+        de.end()?;
+        Ok(())
+    }
+}
+
+/// MessagePack, with structs encoded as maps so field names are kept on the wire.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePackCodec {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> anyhow::Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+    ) -> anyhow::Result<()> {
+        let mut de = rmp_serde::Deserializer::new(bytes);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn decode(
+        &self,
+        mut bytes: &[u8],
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+    ) -> anyhow::Result<()> {
+        // ciborium only deserializes concrete types, so the message goes through its value tree
+        let value: ciborium::value::Value = ciborium::de::from_reader(&mut bytes)?;
+        if !bytes.is_empty() {
+            anyhow::bail!("{} trailing bytes after the CBOR message", bytes.len());
+        }
+        let de = cbor::ValueDeserializer(value);
+        visit(&mut <dyn erased_serde::Deserializer>::erase(de))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cbor")]
+mod cbor {
+    use ciborium::value::Value;
+    use serde::de::{
+        self,
+        value::{Error, MapAccessDeserializer, MapDeserializer, SeqDeserializer},
+        IntoDeserializer, Visitor,
+    };
+    use std::convert::TryFrom;
+
+    /// Deserializer reading a decoded CBOR value.
+    pub(super) struct ValueDeserializer(pub(super) Value);
+
+    impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer {
+        type Deserializer = Self;
+
+        fn into_deserializer(self) -> Self {
+            self
+        }
+    }
+
+    fn entries(
+        map: Vec<(Value, Value)>,
+    ) -> impl Iterator<Item = (ValueDeserializer, ValueDeserializer)> {
+        map.into_iter()
+            .map(|(k, v)| (ValueDeserializer(k), ValueDeserializer(v)))
+    }
+
+    impl<'de> de::Deserializer<'de> for ValueDeserializer {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::Integer(n) => {
+                    let n = i128::from(n);
+                    if let Ok(n) = u64::try_from(n) {
+                        visitor.visit_u64(n)
+                    } else if let Ok(n) = i64::try_from(n) {
+                        visitor.visit_i64(n)
+                    } else {
+                        visitor.visit_i128(n)
+                    }
+                }
+                Value::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+                Value::Float(f) => visitor.visit_f64(f),
+                Value::Text(text) => visitor.visit_string(text),
+                Value::Bool(b) => visitor.visit_bool(b),
+                Value::Null => visitor.visit_unit(),
+                // tags are semantic hints, the tagged value is what matters
+                Value::Tag(_, value) => ValueDeserializer(*value).deserialize_any(visitor),
+                Value::Array(items) => {
+                    let mut seq = SeqDeserializer::new(items.into_iter().map(ValueDeserializer));
+                    let value = visitor.visit_seq(&mut seq)?;
+                    seq.end()?;
+                    Ok(value)
+                }
+                Value::Map(map) => {
+                    let mut map = MapDeserializer::new(entries(map));
+                    let value = visitor.visit_map(&mut map)?;
+                    map.end()?;
+                    Ok(value)
+                }
+                _ => Err(de::Error::custom("unsupported CBOR value")),
+            }
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::Null => visitor.visit_none(),
+                _ => visitor.visit_some(self),
+            }
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        /// Unit variants are encoded as their name, the others as a single entry map.
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::Text(variant) => visitor.visit_enum(variant.into_deserializer()),
+                Value::Map(map) if map.len() == 1 => {
+                    let variant = MapDeserializer::new(entries(map));
+                    visitor.visit_enum(MapAccessDeserializer::new(variant))
+                }
+                _ => Err(de::Error::custom("expected an enum variant")),
+            }
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+            ignored_any
+        }
+    }
+}
+
+/// Compact bincode encoding. It is not self-describing, so both ends must agree on the exact
+/// layout of every message type, and requests can't be sent with it.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl Codec for BincodeCodec {
+    fn name(&self) -> &'static str {
+        "bincode"
+    }
+
+    fn self_describing(&self) -> bool {
+        false
+    }
+
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> anyhow::Result<Vec<u8>> {
+        use dep_bincode::Options;
+        Ok(dep_bincode::options().serialize(value)?)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+    ) -> anyhow::Result<()> {
+        let mut de = dep_bincode::Deserializer::from_slice(bytes, dep_bincode::options());
+        visit(&mut <dyn erased_serde::Deserializer>::erase(&mut de))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::Enveloppe;
+    use serde::Deserialize;
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(f32),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Sample {
+        id: u64,
+        delta: i32,
+        name: String,
+        shapes: Vec<Shape>,
+        note: Option<String>,
+        missing: Option<u8>,
+    }
+
+    impl MessageType for Sample {
+        fn message_type() -> &'static str {
+            "sample"
+        }
+    }
+
+    fn sample() -> Sample {
+        Sample {
+            id: u64::MAX,
+            delta: -42,
+            name: "héllo".to_string(),
+            shapes: vec![Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 3, h: 4 }],
+            note: Some("note".to_string()),
+            missing: None,
+        }
+    }
+
+    fn assert_round_trip(codec: impl Codec) {
+        let codec: Arc<dyn Codec> = Arc::new(codec);
+        let bytes = codec.encode_message(&sample()).unwrap();
+        let enveloppe = Enveloppe::decode(codec.clone(), bytes).unwrap();
+        assert_eq!(enveloppe.message_type, "sample");
+        assert_eq!(enveloppe.correlation_id, None);
+        assert_eq!(enveloppe.payload::<Sample>().unwrap(), sample());
+
+        if codec.self_describing() {
+            let bytes = codec.encode_correlated(&sample(), 7).unwrap();
+            let enveloppe = Enveloppe::decode(codec.clone(), bytes).unwrap();
+            assert_eq!(enveloppe.correlation_id, Some(7));
+            assert_eq!(enveloppe.payload::<Sample>().unwrap(), sample());
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_round_trip(JsonCodec);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip() {
+        assert_round_trip(MessagePackCodec);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_round_trip() {
+        assert_round_trip(CborCodec);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_rejects_trailing_bytes() {
+        let codec: &dyn Codec = &CborCodec;
+        let mut bytes = codec.encode_message(&sample()).unwrap();
+        bytes.push(0);
+        assert!(codec.decode_as::<Sample>(&bytes).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_round_trip() {
+        assert_round_trip(BincodeCodec);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_refuses_correlated_enveloppes() {
+        let codec: &dyn Codec = &BincodeCodec;
+        let e = codec.encode_correlated(&sample(), 7).unwrap_err();
+        assert!(e.to_string().contains("correlation ids"), "{}", e);
+    }
+}
@@ -0,0 +1,50 @@
+use super::MessageType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Standard error reply, sent with the reserved `_error` message type. It carries the
+/// correlation id of the request it answers, if any.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ErrorMessage {
+    /// Machine readable reason, e.g. `lobby_full`.
+    pub code: String,
+    /// Type of the message that caused the error, if known.
+    #[serde(rename = "type", default)]
+    pub message_type: Option<String>,
+    /// Human readable details.
+    pub message: String,
+}
+
+impl ErrorMessage {
+    /// Code of a message whose enveloppe couldn't be read.
+    pub const MALFORMED: &'static str = "malformed";
+    /// Code of a message whose type isn't registered.
+    pub const UNKNOWN_TYPE: &'static str = "unknown_type";
+    /// Code of a message whose payload doesn't match its type.
+    pub const INVALID_PAYLOAD: &'static str = "invalid_payload";
+
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            message_type: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_type(mut self, message_type: impl Into<String>) -> Self {
+        self.message_type = Some(message_type.into());
+        self
+    }
+}
+
+impl fmt::Display for ErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl MessageType for ErrorMessage {
+    fn message_type() -> &'static str {
+        "_error"
+    }
+}
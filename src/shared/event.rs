@@ -1,13 +1,170 @@
 use thiserror::Error as TError;
-use super::ConnectionHandle;
+use tokio_tungstenite::tungstenite::{self, protocol::CloseFrame};
+use super::{CloseCode, ConnectionHandle, ConnectionInfo, ErrorMessage};
+use std::sync::Arc;
 
 #[derive(TError, Debug)]
-pub enum NetworkError {}
+pub enum NetworkError {
+    #[error("websocket handshake failed: {0}")]
+    Handshake(#[source] Box<tungstenite::Error>),
+    #[error("connection rejected with HTTP status {0}")]
+    Rejected(tungstenite::http::StatusCode),
+    #[error("websocket protocol error: {0}")]
+    Protocol(#[from] tungstenite::error::ProtocolError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("websocket error: {0}")]
+    WebSocket(#[source] Box<tungstenite::Error>),
+    #[error("cannot send on closed connection {0:?}")]
+    SendOnClosedConnection(ConnectionHandle),
+    #[error("unknown connection handle {0:?}")]
+    UnknownHandle(ConnectionHandle),
+    #[error("outgoing queue of {0:?} is full")]
+    QueueFull(ConnectionHandle),
+    #[error("received a message of {size} bytes, larger than the {max_size} bytes limit")]
+    MessageTooLarge { size: usize, max_size: usize },
+    #[error("client is not connected")]
+    NotConnected,
+    #[error("failed to encode message: {0}")]
+    Encode(#[source] anyhow::Error),
+    #[error("failed to decode message: {0}")]
+    Decode(#[source] anyhow::Error),
+    #[error("no response received in time")]
+    RequestTimeout,
+    #[error("request failed: {0}")]
+    Remote(ErrorMessage),
+    #[error("server did not acknowledge the {expected} subprotocol, got {acknowledged:?}")]
+    CodecMismatch {
+        expected: String,
+        acknowledged: Option<String>,
+    },
+    #[cfg(feature = "server")]
+    #[error("failed to start server: {0}")]
+    ServerStart(#[from] crate::server::ServerConfigError),
+    #[cfg(feature = "client")]
+    #[error("failed to start client: {0}")]
+    ClientStart(#[from] crate::client::ClientConfigError),
+}
+
+impl NetworkError {
+    /// Classify an error returned while establishing a connection.
+    pub(crate) fn handshake(e: tungstenite::Error) -> Self {
+        match e {
+            tungstenite::Error::Io(e) => NetworkError::Io(e),
+            tungstenite::Error::Http(response) => NetworkError::Rejected(response.status()),
+            e => NetworkError::Handshake(Box::new(e)),
+        }
+    }
+}
+
+impl From<tungstenite::Error> for NetworkError {
+    fn from(e: tungstenite::Error) -> Self {
+        match e {
+            tungstenite::Error::Io(e) => NetworkError::Io(e),
+            tungstenite::Error::Protocol(e) => NetworkError::Protocol(e),
+            tungstenite::Error::Capacity(tungstenite::error::CapacityError::MessageTooLong {
+                size,
+                max_size,
+            }) => NetworkError::MessageTooLarge { size, max_size },
+            e => NetworkError::WebSocket(Box::new(e)),
+        }
+    }
+}
+
+/// Why a connection ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The peer closed the connection. `code` is `CloseCode::Status` if it didn't provide one.
+    Closed { code: CloseCode, reason: String },
+    /// The server closed the connection through `Server::disconnect`.
+    Kicked { code: CloseCode, reason: String },
+    /// The client closed the connection through `Client::close`, or was dropped.
+    ClosedLocally { code: CloseCode, reason: String },
+    /// The server was stopped or dropped, and closed the connection with `CloseCode::Away`.
+    Shutdown,
+    /// The peer stopped responding.
+    Timeout,
+    /// The peer didn't read its messages fast enough, and its outgoing queue overflowed.
+    SlowConsumer,
+    /// The peer sent a message or frame larger than the configured limits.
+    MessageTooLarge,
+    /// The peer violated the websocket protocol.
+    ProtocolError(String),
+    /// The underlying stream failed or ended without a close frame.
+    IoError(String),
+}
+
+impl DisconnectReason {
+    pub(crate) fn closed(frame: Option<CloseFrame>) -> Self {
+        let (code, reason) = close_frame_parts(frame);
+        DisconnectReason::Closed { code, reason }
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn kicked(frame: Option<CloseFrame>) -> Self {
+        let (code, reason) = close_frame_parts(frame);
+        DisconnectReason::Kicked { code, reason }
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn closed_locally(frame: Option<CloseFrame>) -> Self {
+        let (code, reason) = close_frame_parts(frame);
+        DisconnectReason::ClosedLocally { code, reason }
+    }
+}
+
+fn close_frame_parts(frame: Option<CloseFrame>) -> (CloseCode, String) {
+    match frame {
+        Some(frame) => (frame.code, frame.reason.into_owned()),
+        None => (CloseCode::Status, String::new()),
+    }
+}
+
+impl From<&tungstenite::Error> for DisconnectReason {
+    fn from(e: &tungstenite::Error) -> Self {
+        match e {
+            tungstenite::Error::Io(e) => DisconnectReason::IoError(e.to_string()),
+            e @ tungstenite::Error::Protocol(
+                tungstenite::error::ProtocolError::ResetWithoutClosingHandshake,
+            ) => DisconnectReason::IoError(e.to_string()),
+            tungstenite::Error::Capacity(tungstenite::error::CapacityError::MessageTooLong {
+                ..
+            }) => DisconnectReason::MessageTooLarge,
+            e => DisconnectReason::ProtocolError(e.to_string()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum NetworkEvent {
-    Connected(ConnectionHandle),
-    Disconnected(ConnectionHandle),
+    Connected(ConnectionHandle, Arc<ConnectionInfo>),
+    /// Emitted exactly once per connection, however it ended.
+    Disconnected(ConnectionHandle, DisconnectReason),
+    /// A client lost its connection and is about to re-dial, carries the attempt number.
+    Reconnecting(ConnectionHandle, u32),
     Message(ConnectionHandle, Vec<u8>),
-    Error(Option<ConnectionHandle>, anyhow::Error),
+    /// Carries no handle when the failure happened before the connection was announced by a
+    /// `Connected` event, e.g. a refused or failed handshake.
+    Error(Option<ConnectionHandle>, NetworkError),
+}
+
+/// Websocket frame type used to send encoded messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameKind {
+    #[default]
+    Binary,
+    /// Text frames, falling back to binary for payloads that aren't valid UTF-8.
+    Text,
+}
+
+impl FrameKind {
+    pub(crate) fn frame(self, payload: Vec<u8>) -> tungstenite::Message {
+        match self {
+            FrameKind::Binary => tungstenite::Message::Binary(payload),
+            FrameKind::Text => match String::from_utf8(payload) {
+                Ok(text) => tungstenite::Message::Text(text),
+                Err(e) => tungstenite::Message::Binary(e.into_bytes()),
+            },
+        }
+    }
 }
@@ -0,0 +1,41 @@
+use std::{collections::HashMap, net::SocketAddr, time::SystemTime};
+use tokio_tungstenite::tungstenite::http::{HeaderMap, Uri};
+
+/// Who a connection was authenticated as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub id: String,
+    /// Free-form attributes, e.g. roles or a display name.
+    pub attributes: HashMap<String, String>,
+}
+
+impl Identity {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            attributes: HashMap::new(),
+        }
+    }
+
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// What is known about a connection once its handshake completed.
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// Address of the peer, `None` if the socket couldn't tell.
+    pub remote_addr: Option<SocketAddr>,
+    /// URI of the upgrade request, with its path and query string.
+    pub uri: Uri,
+    /// Headers of the handshake kept for later use: the request headers listed in
+    /// `ServerConfig::record_headers` on a server, every response header on a client.
+    pub headers: HeaderMap,
+    /// Subprotocol agreed on during the handshake.
+    pub subprotocol: Option<String>,
+    pub connected_at: SystemTime,
+    /// Identity given by the server authenticator.
+    pub identity: Option<Identity>,
+}
@@ -0,0 +1,170 @@
+use std::{
+    convert::TryInto,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::{interval_at, Interval, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Heartbeat settings applied to each connection.
+#[derive(Debug, Clone)]
+pub struct KeepaliveConfig {
+    /// Send a websocket Ping frame this often.
+    pub ping_interval: Duration,
+    /// Disconnect if no Pong is received this long after a Ping.
+    pub pong_timeout: Duration,
+    /// Disconnect if nothing at all is received for this long.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(15),
+            pong_timeout: Duration::from_secs(10),
+            idle_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Round-trip time of a connection, measured with Ping/Pong frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttStats {
+    /// Smoothed round-trip time.
+    pub rtt: Duration,
+    /// Smoothed deviation of the round-trip time.
+    pub jitter: Duration,
+    /// Most recent sample.
+    pub last: Duration,
+    pub samples: u32,
+}
+
+impl RttStats {
+    // RFC 6298 smoothing
+    fn update(stats: Option<RttStats>, sample: Duration) -> RttStats {
+        match stats {
+            None => RttStats {
+                rtt: sample,
+                jitter: sample / 2,
+                last: sample,
+                samples: 1,
+            },
+            Some(s) => {
+                let deviation = s.rtt.abs_diff(sample);
+                RttStats {
+                    rtt: (s.rtt * 7 + sample) / 8,
+                    jitter: (s.jitter * 3 + deviation) / 4,
+                    last: sample,
+                    samples: s.samples.saturating_add(1),
+                }
+            }
+        }
+    }
+}
+
+pub(crate) enum Heartbeat {
+    Wait,
+    /// Send a Ping frame with this payload.
+    Ping(Vec<u8>),
+    TimedOut,
+}
+
+struct State {
+    last_seen: Instant,
+    last_ping: Instant,
+    awaiting_pong: Option<Instant>,
+    rtt: Option<RttStats>,
+}
+
+/// Keepalive bookkeeping of a single connection, shared by its read and write halves.
+pub(crate) struct Keepalive {
+    config: KeepaliveConfig,
+    created: Instant,
+    state: Mutex<State>,
+}
+
+impl Keepalive {
+    pub(crate) fn new(config: KeepaliveConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            created: now,
+            state: Mutex::new(State {
+                last_seen: now,
+                last_ping: now,
+                awaiting_pong: None,
+                rtt: None,
+            }),
+        }
+    }
+
+    pub(crate) fn rtt(&self) -> Option<RttStats> {
+        self.state.lock().unwrap().rtt
+    }
+
+    /// Timer at which [`tick`](#method.tick) must be called, fine enough for every deadline.
+    pub(crate) fn interval(&self) -> Interval {
+        let period = [
+            Some(self.config.ping_interval),
+            Some(self.config.pong_timeout),
+            self.config.idle_timeout,
+        ]
+        .iter()
+        .flatten()
+        .min()
+        .copied()
+        .unwrap_or(self.config.ping_interval)
+        .max(Duration::from_millis(10));
+        let mut interval = interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    }
+
+    /// Record a frame received from the peer.
+    pub(crate) fn on_frame(&self, msg: &Message) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.last_seen = now;
+        if let Message::Pong(payload) = msg {
+            state.awaiting_pong = None;
+            // our pings carry the time they were sent at, ignore unsolicited pongs
+            let sent = payload
+                .as_slice()
+                .try_into()
+                .ok()
+                .map(|nanos| self.created + Duration::from_nanos(u64::from_be_bytes(nanos)));
+            if let Some(sent) = sent.filter(|sent| *sent <= now) {
+                state.rtt = Some(RttStats::update(state.rtt, now - sent));
+            }
+        }
+    }
+
+    /// Wait for the next tick of a timer built by [`interval`](#method.interval), forever if
+    /// there is none.
+    pub(crate) async fn next_tick(ticker: &mut Option<Interval>) {
+        match ticker {
+            Some(ticker) => {
+                ticker.tick().await;
+            }
+            None => futures::future::pending().await,
+        }
+    }
+
+    pub(crate) fn tick(&self) -> Heartbeat {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if matches!(self.config.idle_timeout, Some(idle) if now - state.last_seen >= idle) {
+            return Heartbeat::TimedOut;
+        }
+        if matches!(state.awaiting_pong, Some(sent) if now - sent >= self.config.pong_timeout) {
+            return Heartbeat::TimedOut;
+        }
+        if now - state.last_ping >= self.config.ping_interval {
+            state.last_ping = now;
+            state.awaiting_pong.get_or_insert(now);
+            let nanos = (now - self.created).as_nanos() as u64;
+            return Heartbeat::Ping(nanos.to_be_bytes().to_vec());
+        }
+        Heartbeat::Wait
+    }
+}
@@ -0,0 +1,37 @@
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+/// Size limits applied to each connection.
+///
+/// A peer sending a message or a frame over the limits is disconnected with the `Size` close
+/// code.
+#[derive(Debug, Clone)]
+pub struct MessageLimits {
+    /// Maximum size of a received message, in bytes. `None` means no limit.
+    pub max_message_size: Option<usize>,
+    /// Maximum payload size of a received frame, in bytes. `None` means no limit.
+    pub max_frame_size: Option<usize>,
+    /// Number of frames buffered for writing before sends wait for the socket. `None` means no
+    /// limit.
+    pub max_send_queue: Option<usize>,
+}
+
+impl Default for MessageLimits {
+    fn default() -> Self {
+        Self {
+            max_message_size: Some(64 << 20),
+            max_frame_size: Some(16 << 20),
+            max_send_queue: None,
+        }
+    }
+}
+
+impl MessageLimits {
+    pub(crate) fn websocket_config(&self) -> WebSocketConfig {
+        WebSocketConfig {
+            max_send_queue: self.max_send_queue,
+            max_message_size: self.max_message_size,
+            max_frame_size: self.max_frame_size,
+            ..WebSocketConfig::default()
+        }
+    }
+}
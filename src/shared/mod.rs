@@ -1,10 +1,44 @@
+mod channel;
+mod codec;
+mod error_message;
 mod handle;
+mod info;
 mod router;
 mod event;
+mod keepalive;
+mod limits;
+mod runtime;
 
+pub use codec::*;
+pub use error_message::ErrorMessage;
 pub use handle::ConnectionHandle;
+pub use info::{ConnectionInfo, Identity};
 pub use router::*;
 pub use event::*;
+pub use keepalive::{KeepaliveConfig, RttStats};
+pub use limits::MessageLimits;
+pub use runtime::SharedRuntime;
+pub(crate) use keepalive::{Heartbeat, Keepalive};
+pub(crate) use channel::{event_channel, EventReceiver, EventSender};
+pub use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+/// How long a closing side waits for the peer to acknowledge its close frame.
+pub(crate) const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Run a connection handshake, failing with a `TimedOut` I/O error once `deadline` passed.
+pub(crate) async fn with_handshake_deadline<T, E: From<std::io::Error>>(
+    deadline: Option<tokio::time::Instant>,
+    handshake: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, handshake)
+            .await
+            .unwrap_or_else(|_| {
+                Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "handshake timed out").into())
+            }),
+        None => handshake.await,
+    }
+}
 
 #[macro_export]
 macro_rules! impl_message_type {
@@ -1,13 +1,68 @@
-use serde::{Deserialize, Serialize};
+use super::Codec;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::any::Any;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// A received message whose type tag has been read, but not its payload yet.
+#[derive(Clone, Debug)]
 pub struct Enveloppe {
-    #[serde(rename(serialize = "t", deserialize = "t"))]
     pub message_type: String,
-    #[serde(rename(serialize = "d", deserialize = "d"))]
-    pub payload: Box<serde_json::value::RawValue>,
+    /// The whole message, as received.
+    pub raw: Vec<u8>,
+    /// Codec the message was encoded with.
+    pub codec: Arc<dyn Codec>,
+    /// Id pairing a request with its response, see `Server::respond`.
+    pub correlation_id: Option<u64>,
+}
+
+impl Enveloppe {
+    pub fn decode(codec: Arc<dyn Codec>, raw: Vec<u8>) -> anyhow::Result<Self> {
+        let tag = if codec.self_describing() {
+            codec.decode_as::<Tag>(&raw)?
+        } else {
+            let tag = codec.decode_as::<TypeTag>(&raw)?;
+            Tag {
+                message_type: tag.message_type,
+                correlation_id: None,
+            }
+        };
+        Ok(Self {
+            message_type: tag.message_type,
+            raw,
+            codec,
+            correlation_id: tag.correlation_id,
+        })
+    }
+
+    /// Decode the payload of the message, whatever its type tag.
+    pub fn payload<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        Ok(self.codec.decode_as::<Payload<T>>(&self.raw)?.payload)
+    }
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    #[serde(rename = "t")]
+    message_type: String,
+    #[serde(rename = "i", default)]
+    correlation_id: Option<u64>,
+}
+
+/// Type tag of codecs that can't skip fields, which never carry a correlation id.
+#[derive(Deserialize)]
+struct TypeTag {
+    #[serde(rename = "t")]
+    message_type: String,
+}
+
+/// Received message without its optional fields, readable by every codec.
+#[derive(Deserialize)]
+struct Payload<T> {
+    #[serde(rename = "t")]
+    _message_type: String,
+    #[serde(rename = "d")]
+    payload: T,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -16,19 +71,27 @@ pub struct SendEnveloppe<T> {
     pub message_type: String,
     #[serde(rename(serialize = "d", deserialize = "d"))]
     pub payload: T,
+    #[serde(
+        rename(serialize = "i", deserialize = "i"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub correlation_id: Option<u64>,
 }
 
 pub trait MessageType: Any + serde::de::DeserializeOwned + Send + Sync {
     fn message_type() -> &'static str;
 }
 
-type Df = Box<dyn Send + Fn(&serde_json::value::RawValue) -> anyhow::Result<Box<dyn Any + Send>>>;
+type Df = Box<dyn Send + Fn(&dyn Codec, &[u8]) -> anyhow::Result<Box<dyn Any + Send>>>;
 
-fn generate_deserialize_fn<T: Any>() -> Df
+fn generate_deserialize_fn<T>() -> Df
 where
-    T: serde::de::DeserializeOwned + Send,
+    T: Any + serde::de::DeserializeOwned + Send,
 {
-    Box::new(|v: &serde_json::value::RawValue| Ok(Box::new(serde_json::from_str::<T>(v.get())?)))
+    Box::new(|codec: &dyn Codec, v: &[u8]| {
+        Ok(Box::new(codec.decode_as::<Payload<T>>(v)?.payload))
+    })
 }
 
 #[derive(Default)]
@@ -45,30 +108,33 @@ impl GenericParser {
 
     pub fn insert_type<T: MessageType>(&mut self) {
         let tag = T::message_type();
-        if self.tps.get(tag).is_some() {
+        if self.tps.contains_key(tag) {
             panic!("type '{}' already registered", tag);
         }
         self.tps
             .insert(tag.to_string(), Box::new(generate_deserialize_fn::<T>()));
     }
 
+    pub fn has_type(&self, tag: &str) -> bool {
+        self.tps.contains_key(tag)
+    }
+
     pub fn parse_as_any(
         &self,
         tag: &str,
-        dat: &serde_json::value::RawValue,
+        codec: &dyn Codec,
+        dat: &[u8],
     ) -> anyhow::Result<Box<dyn Any + Send>> {
         match self.tps.get(tag) {
-            Some(func) => {
-                return func
-                    .downcast_ref::<Df>()
-                    .expect("failed to load downcast function")(dat);
-            }
+            Some(func) => func
+                .downcast_ref::<Df>()
+                .expect("failed to load downcast function")(codec, dat),
             None => anyhow::bail!("type '{}' not registered", tag),
         }
     }
 
     pub fn parse_enveloppe(&self, ev: &Enveloppe) -> anyhow::Result<Box<dyn Any + Send>> {
-        self.parse_as_any(&ev.message_type, &ev.payload)
+        self.parse_as_any(&ev.message_type, &*ev.codec, &ev.raw)
     }
 
     pub fn try_into_concrete_type<T: 'static>(d: Box<dyn Any + Send>) -> anyhow::Result<T> {
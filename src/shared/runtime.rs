@@ -0,0 +1,107 @@
+use std::{fmt, num::NonZeroUsize, sync::Arc};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// Tokio runtime driving the connections, which can be shared by any number of servers and
+/// clients.
+///
+/// Either owns a multi-thread runtime, which is shut down once the last clone is dropped, or
+/// borrows the runtime of the application through its [`Handle`].
+#[derive(Clone)]
+pub struct SharedRuntime {
+    handle: Handle,
+    /// Keeps an owned runtime alive.
+    _runtime: Option<Arc<Runtime>>,
+}
+
+impl Default for SharedRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedRuntime {
+    /// Multi-thread runtime with one worker per core.
+    pub fn new() -> Self {
+        Self::build(None)
+    }
+
+    /// Multi-thread runtime with the given number of workers.
+    pub fn with_worker_threads(threads: NonZeroUsize) -> Self {
+        Self::build(Some(threads))
+    }
+
+    /// Run on an existing runtime, which must outlive every server and client using it.
+    pub fn from_handle(handle: Handle) -> Self {
+        Self {
+            handle,
+            _runtime: None,
+        }
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Whether the runtime is owned, and thus always a multi-thread one.
+    #[cfg(feature = "server")]
+    pub(crate) fn is_owned(&self) -> bool {
+        self._runtime.is_some()
+    }
+
+    pub(crate) fn build(worker_threads: Option<NonZeroUsize>) -> Self {
+        let mut builder = Builder::new_multi_thread();
+        if let Some(threads) = worker_threads {
+            builder.worker_threads(threads.get());
+        }
+        let runtime = builder
+            .enable_all()
+            .build()
+            .expect("Could not build tokio runtime");
+        Self {
+            handle: runtime.handle().clone(),
+            _runtime: Some(Arc::new(runtime)),
+        }
+    }
+}
+
+impl Drop for SharedRuntime {
+    fn drop(&mut self) {
+        // the last clone can be dropped from an async context, e.g. by a task of the runtime,
+        // where shutting it down in place would panic
+        if let Some(runtime) = self._runtime.take().and_then(Arc::into_inner) {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl fmt::Debug for SharedRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedRuntime")
+            .field("owned", &self._runtime.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn owned_runtime_can_be_dropped_from_async_code() {
+        let app = Builder::new_current_thread().build().unwrap();
+        let rt = SharedRuntime::with_worker_threads(NonZeroUsize::new(1).unwrap());
+        app.block_on(async move { drop(rt) });
+    }
+
+    #[test]
+    fn owned_runtime_can_be_dropped_by_its_own_task() {
+        let rt = SharedRuntime::new();
+        let (done_tx, done_rx) = mpsc::channel();
+        rt.handle().clone().spawn(async move {
+            drop(rt);
+            done_tx.send(()).unwrap();
+        });
+        done_rx.recv_timeout(std::time::Duration::from_secs(10)).unwrap();
+    }
+}
@@ -16,6 +16,7 @@ def on_open(ws):
         for i in range(3):
             time.sleep(1)
             ws.send(bytes("{ \"t\": \"ping\", \"d\": {}}", 'utf-8'), websocket.ABNF.OPCODE_BINARY)
+            ws.send("{ \"t\": \"ping\", \"d\": {}}")
         time.sleep(1)
         ws.close()
         print("thread terminating...")
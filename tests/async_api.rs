@@ -0,0 +1,56 @@
+#![cfg(all(feature = "server", feature = "client"))]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    client::{Client, ClientConfig},
+    server::{Server, ServerConfig},
+    shared::{CloseCode, DisconnectReason, Enveloppe, NetworkEvent},
+};
+use common::{listen, Greeting};
+use futures::StreamExt;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_and_messages_are_awaited() {
+    let mut server = Server::with_handle(ServerConfig::default(), Handle::current());
+    let url = listen(&mut server);
+    let mut client = Client::with_handle(ClientConfig::default(), Handle::current());
+    client.connect(url).unwrap();
+
+    let exchange = async {
+        let mut events = server.events();
+        let handle = match events.next().await.unwrap() {
+            NetworkEvent::Connected(handle, _) => handle,
+            other => panic!("expected a connection, got {:?}", other),
+        };
+        client.send(&Greeting { n: 1 }).await.unwrap();
+        match events.next().await.unwrap() {
+            NetworkEvent::Message(from, bytes) => {
+                assert_eq!(from, handle);
+                let enveloppe = Enveloppe::decode(server.codec_for(&handle), bytes).unwrap();
+                assert_eq!(enveloppe.payload::<Greeting>().unwrap(), Greeting { n: 1 });
+            }
+            other => panic!("expected a message, got {:?}", other),
+        }
+
+        // the Connected event is skipped
+        server.send(&handle, &Greeting { n: 2 }).await.unwrap();
+        assert_eq!(client.recv::<Greeting>().await.unwrap(), Greeting { n: 2 });
+
+        // the events of the client end with the connection
+        client.close(CloseCode::Normal, "done").unwrap();
+        let rest = client.events().collect::<Vec<_>>().await;
+        match rest.as_slice() {
+            [NetworkEvent::Disconnected(_, DisconnectReason::ClosedLocally { code, .. })] => {
+                assert_eq!(*code, CloseCode::Normal)
+            }
+            other => panic!("expected a single disconnection, got {:?}", other),
+        }
+        assert!(client.recv::<Greeting>().await.is_err());
+    };
+    tokio::time::timeout(Duration::from_secs(10), exchange)
+        .await
+        .expect("test timed out");
+}
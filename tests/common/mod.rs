@@ -0,0 +1,55 @@
+//! Helpers shared by the integration tests, each of which uses only some of them.
+#![allow(dead_code)]
+
+#[cfg(feature = "client")]
+use bevy_websocket_adapter::client::Client;
+#[cfg(feature = "server")]
+use bevy_websocket_adapter::server::Server;
+#[cfg(all(feature = "server", feature = "client"))]
+use bevy_websocket_adapter::shared::{ConnectionHandle, NetworkEvent};
+use bevy_websocket_adapter::impl_message_type;
+use serde::{Deserialize, Serialize};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Greeting {
+    pub n: u32,
+}
+impl_message_type!(Greeting, "greeting");
+
+/// Poll `next` until it yields a value, for at most 10 seconds.
+pub fn wait_for<T>(mut next: impl FnMut() -> Option<T>) -> T {
+    let start = Instant::now();
+    loop {
+        if let Some(v) = next() {
+            return v;
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "test timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Start `server` on a free local port, returns its `ws://` URL.
+#[cfg(feature = "server")]
+pub fn listen(server: &mut Server) -> String {
+    server.listen("127.0.0.1:0").unwrap();
+    format!("ws://{}", server.local_addr().unwrap())
+}
+
+/// Connect `client` to `url`, served by `server`, and wait until both ends saw the connection.
+/// Returns the handle of the client on the server.
+#[cfg(all(feature = "server", feature = "client"))]
+pub fn connect(server: &Server, client: &mut Client, url: &str) -> ConnectionHandle {
+    client.connect(url.to_string()).unwrap();
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Connected(..) => {}
+        other => panic!("expected the client to connect, got {:?}", other),
+    }
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Connected(handle, _) => handle,
+        other => panic!("expected a connection, got {:?}", other),
+    }
+}
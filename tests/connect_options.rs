@@ -0,0 +1,91 @@
+#![cfg(all(feature = "server", feature = "client"))]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    client::{Client, ClientConfigError, ConnectOptions},
+    server::{Identity, Rejection, Request, Server, ServerConfig},
+    shared::{Codec, JsonCodec, NetworkError, NetworkEvent},
+};
+use common::{listen, wait_for};
+use std::{
+    io::ErrorKind,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tungstenite::http::header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL};
+
+#[test]
+fn options_shape_the_upgrade_request() {
+    let seen = Arc::new(Mutex::new(None));
+    let authenticate = {
+        let seen = seen.clone();
+        move |request: &Request| {
+            *seen.lock().unwrap() = Some((request.uri().clone(), request.headers().clone()));
+            Ok::<_, Rejection>(Identity::new("alice"))
+        }
+    };
+    let mut server = Server::with_config(ServerConfig::default().authenticate(authenticate));
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    let options = ConnectOptions::default()
+        .bearer_token("secret")
+        .header("X-Api-Key", "key")
+        .query("room", "red")
+        .subprotocol("chat.v1");
+    client.connect_with(url, options).unwrap();
+
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Connected(_, info) => {
+            // the server still picks the codec
+            assert_eq!(info.subprotocol, Some(JsonCodec.subprotocol()))
+        }
+        other => panic!("expected the client to connect, got {:?}", other),
+    }
+    let (uri, headers) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(uri.query(), Some("room=red"));
+    assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+    assert_eq!(headers["x-api-key"], "key");
+    assert_eq!(
+        headers[SEC_WEBSOCKET_PROTOCOL],
+        format!("{}, chat.v1", JsonCodec.subprotocol()).as_str()
+    );
+}
+
+#[test]
+fn invalid_options_are_refused() {
+    let mut client = Client::new();
+    let url = "ws://127.0.0.1:1".to_string();
+
+    let options = ConnectOptions::default().header("X-Api-Key", "line\nbreak");
+    match client.connect_with(url.clone(), options) {
+        Err(ClientConfigError::InvalidHeader(name)) => assert_eq!(name, "X-Api-Key"),
+        other => panic!("expected an invalid header, got {:?}", other),
+    }
+    let options = ConnectOptions::default().subprotocol("line\nbreak");
+    match client.connect_with(url, options) {
+        Err(ClientConfigError::InvalidHeader(name)) => {
+            assert_eq!(name, SEC_WEBSOCKET_PROTOCOL.as_str())
+        }
+        other => panic!("expected an invalid subprotocol, got {:?}", other),
+    }
+}
+
+#[test]
+fn silent_server_times_out() {
+    // accepts the connection, never answers the upgrade
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let mut client = Client::new();
+    let options = ConnectOptions::default().timeout(Duration::from_millis(100));
+
+    let start = Instant::now();
+    client.connect_with(url, options).unwrap();
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Error(_, NetworkError::Io(e)) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+    drop(listener);
+}
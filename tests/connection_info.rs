@@ -0,0 +1,67 @@
+#![cfg(all(feature = "server", feature = "client"))]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    client::{Client, ConnectOptions},
+    server::{Server, ServerConfig},
+    shared::{Codec, JsonCodec, NetworkEvent},
+};
+use common::{listen, wait_for};
+use std::{sync::Arc, time::SystemTime};
+use tungstenite::http::header::{HeaderName, SEC_WEBSOCKET_PROTOCOL, USER_AGENT};
+
+#[test]
+fn both_ends_describe_the_connection() {
+    let team = HeaderName::from_static("x-team");
+    let config = ServerConfig::default().record_headers(vec![USER_AGENT, team.clone()]);
+    let mut server = Server::with_config(config);
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    let options = ConnectOptions::default()
+        .header("User-Agent", "tester")
+        .header("X-Team", "red")
+        .header("X-Secret", "hunter2");
+
+    let before = SystemTime::now();
+    client
+        .connect_with(format!("{}/lobby?room=1", url), options)
+        .unwrap();
+    let client_info = match wait_for(|| client.try_recv()) {
+        NetworkEvent::Connected(_, info) => info,
+        other => panic!("expected the client to connect, got {:?}", other),
+    };
+    let (handle, server_info) = match wait_for(|| server.recv()) {
+        NetworkEvent::Connected(handle, info) => (handle, info),
+        other => panic!("expected a connection, got {:?}", other),
+    };
+    let after = SystemTime::now();
+    let subprotocol = Some(JsonCodec.subprotocol());
+
+    assert!(Arc::ptr_eq(&server.connection_info(&handle).unwrap(), &server_info));
+    let remote = server_info.remote_addr.unwrap();
+    assert!(remote.ip().is_loopback());
+    assert_ne!(remote.port(), server.local_addr().unwrap().port());
+    assert_eq!(server_info.uri.path(), "/lobby");
+    assert_eq!(server_info.uri.query(), Some("room=1"));
+    // only the recorded headers are kept
+    assert_eq!(server_info.headers.len(), 2);
+    assert_eq!(server_info.headers[USER_AGENT], "tester");
+    assert_eq!(server_info.headers[&team], "red");
+    assert_eq!(server_info.subprotocol, subprotocol);
+    assert!(before <= server_info.connected_at && server_info.connected_at <= after);
+    assert!(server_info.identity.is_none());
+
+    assert!(Arc::ptr_eq(&client.connection_info().unwrap(), &client_info));
+    assert_eq!(client_info.remote_addr, server.local_addr());
+    assert_eq!(client_info.uri.path(), "/lobby");
+    assert_eq!(client_info.uri.query(), Some("room=1"));
+    // every response header is kept
+    assert_eq!(
+        client_info.headers[SEC_WEBSOCKET_PROTOCOL],
+        JsonCodec.subprotocol().as_str()
+    );
+    assert_eq!(client_info.subprotocol, subprotocol);
+    assert!(before <= client_info.connected_at && client_info.connected_at <= after);
+    assert!(client_info.identity.is_none());
+}
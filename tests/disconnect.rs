@@ -0,0 +1,108 @@
+#![cfg(all(feature = "server", feature = "client"))]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    client::Client,
+    server::Server,
+    shared::{CloseCode, DisconnectReason, NetworkEvent},
+};
+use common::{connect, listen, wait_for, Greeting};
+
+fn assert_shut_down(client: &Client) {
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Disconnected(_, DisconnectReason::Closed { code, .. }) => {
+            assert_eq!(code, CloseCode::Away)
+        }
+        other => panic!("expected the server to close the connection, got {:?}", other),
+    }
+}
+
+#[test]
+fn disconnect_flushes_the_queue_then_closes() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    let handle = connect(&server, &mut client, &url);
+    for n in 0..3 {
+        server.send_message(&handle, &Greeting { n }).unwrap();
+    }
+    server.disconnect(&handle, CloseCode::Normal, "bye").unwrap();
+    assert!(server.send_message(&handle, &Greeting { n: 3 }).is_err());
+
+    for n in 0..3 {
+        match wait_for(|| client.try_recv()) {
+            NetworkEvent::Message(_, bytes) => {
+                let v: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                assert_eq!(v["d"]["n"], n);
+            }
+            other => panic!("expected message {}, got {:?}", n, other),
+        }
+    }
+    let bye = || ("bye".to_string(), CloseCode::Normal);
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Disconnected(_, DisconnectReason::Closed { code, reason }) => {
+            assert_eq!((reason, code), bye())
+        }
+        other => panic!("expected a disconnection, got {:?}", other),
+    }
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Disconnected(h, DisconnectReason::Kicked { code, reason }) => {
+            assert_eq!(h, handle);
+            assert_eq!((reason, code), bye());
+        }
+        other => panic!("expected a disconnection, got {:?}", other),
+    }
+}
+
+#[test]
+fn client_close_is_reported_as_local() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    connect(&server, &mut client, &url);
+    client.close(CloseCode::Away, "leaving").unwrap();
+
+    let leaving = || ("leaving".to_string(), CloseCode::Away);
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Disconnected(_, DisconnectReason::ClosedLocally { code, reason }) => {
+            assert_eq!((reason, code), leaving())
+        }
+        other => panic!("expected a local close, got {:?}", other),
+    }
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Disconnected(_, DisconnectReason::Closed { code, reason }) => {
+            assert_eq!((reason, code), leaving())
+        }
+        other => panic!("expected a disconnection, got {:?}", other),
+    }
+}
+
+#[test]
+fn stopping_the_server_closes_its_connections() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    let handle = connect(&server, &mut client, &url);
+
+    server.stop();
+    assert_shut_down(&client);
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Disconnected(h, reason) => {
+            assert_eq!(h, handle);
+            assert_eq!(reason, DisconnectReason::Shutdown);
+        }
+        other => panic!("expected a shutdown, got {:?}", other),
+    }
+}
+
+#[test]
+fn dropping_the_server_closes_its_connections() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    connect(&server, &mut client, &url);
+
+    drop(server);
+    assert_shut_down(&client);
+}
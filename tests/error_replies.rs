@@ -0,0 +1,74 @@
+#![cfg(all(feature = "bevy-plugin", feature = "server"))]
+
+mod common;
+
+use ::bevy::prelude::*;
+use bevy_websocket_adapter::{
+    bevy::{WebSocketServer, WsMessageInserter},
+    server::{Server, ServerConfig},
+    shared::ErrorMessage,
+};
+use common::{listen, wait_for, Greeting};
+use std::{collections::HashMap, net::TcpStream, sync::mpsc, thread, time::Duration};
+use tungstenite::Message;
+
+#[test]
+fn undeliverable_messages_are_answered_with_errors() {
+    let mut builder = App::build();
+    builder
+        .add_plugin(WebSocketServer::with_config(
+            ServerConfig::default().report_errors(true),
+        ))
+        .add_message_type::<Greeting>();
+    let url = listen(&mut builder.world_mut().get_resource_mut::<Server>().unwrap());
+    let mut app = builder.app;
+    let addr = app.world.get_resource::<Server>().unwrap().local_addr().unwrap();
+
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let (mut ws, _) = tungstenite::client(url.as_str(), stream).unwrap();
+    for text in &[
+        r#"{"t":"chat","d":{"text":"hi"},"i":7}"#,
+        r#"{"t":"greeting","d":{"n":"one"},"i":8}"#,
+        "not json",
+    ] {
+        ws.write_message(Message::Text(text.to_string())).unwrap();
+    }
+
+    // the replies are only sent while the app runs
+    let (tx, rx) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        for _ in 0..3 {
+            let reply = loop {
+                match ws.read_message().expect("failed to read reply") {
+                    Message::Text(text) => break text.into_bytes(),
+                    Message::Binary(bytes) => break bytes,
+                    _ => {}
+                }
+            };
+            let v: serde_json::Value = serde_json::from_slice(&reply).unwrap();
+            tx.send(v).unwrap();
+        }
+    });
+    let mut replies = HashMap::new();
+    while replies.len() < 3 {
+        let v = wait_for(|| {
+            app.update();
+            rx.try_recv().ok()
+        });
+        assert_eq!(v["t"], "_error");
+        let error: ErrorMessage = serde_json::from_value(v["d"].clone()).unwrap();
+        replies.insert(error.code.clone(), (v["i"].as_u64(), error));
+    }
+    reader.join().unwrap();
+
+    let (id, error) = &replies[ErrorMessage::UNKNOWN_TYPE];
+    assert_eq!(*id, Some(7));
+    assert_eq!(error.message_type.as_deref(), Some("chat"));
+    let (id, error) = &replies[ErrorMessage::INVALID_PAYLOAD];
+    assert_eq!(*id, Some(8));
+    assert_eq!(error.message_type.as_deref(), Some("greeting"));
+    let (id, error) = &replies[ErrorMessage::MALFORMED];
+    assert_eq!(*id, None);
+    assert_eq!(error.message_type, None);
+}
@@ -0,0 +1,143 @@
+#![cfg(feature = "bevy-plugin")]
+
+mod common;
+
+use ::bevy::prelude::*;
+use bevy_websocket_adapter::{
+    bevy::{WebSocketClient, WebSocketServer},
+    client::Client,
+    server::Server,
+    shared::NetworkEvent,
+};
+use common::{listen, wait_for, Greeting};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    handshake::server::{Request, Response},
+    http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue},
+    Message, WebSocket,
+};
+
+/// Sends must reach the peer well before this, without any other traffic waking the session.
+const FLUSH_DEADLINE: Duration = Duration::from_millis(500);
+
+fn greet_clients(mut evs: EventReader<NetworkEvent>, server: Res<Server>) {
+    for ev in evs.iter() {
+        if let NetworkEvent::Connected(handle, _) = ev {
+            for n in 0..3 {
+                server.send_message(handle, &Greeting { n }).unwrap();
+            }
+        }
+    }
+}
+
+fn greet_server(mut evs: EventReader<NetworkEvent>, client: Res<Client>) {
+    for ev in evs.iter() {
+        if let NetworkEvent::Connected(..) = ev {
+            for n in 0..3 {
+                client.send_message(&Greeting { n }).unwrap();
+            }
+        }
+    }
+}
+
+/// Run the app until `done` yields a value.
+fn update_until<T>(app: &mut App, done: &mpsc::Receiver<T>) -> T {
+    wait_for(|| {
+        app.update();
+        done.try_recv().ok()
+    })
+}
+
+/// Read `count` greetings, along with the time each one arrived at.
+fn read_greetings<S: Read + Write>(ws: &mut WebSocket<S>, count: u32) -> Vec<(u32, Instant)> {
+    let mut received = Vec::new();
+    while received.len() < count as usize {
+        match ws.read_message().expect("failed to read message") {
+            Message::Binary(bytes) => {
+                let v: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                assert_eq!(v["t"], "greeting");
+                received.push((v["d"]["n"].as_u64().unwrap() as u32, Instant::now()));
+            }
+            Message::Ping(_) | Message::Pong(_) => {}
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+    received
+}
+
+#[test]
+fn server_sends_from_systems_are_flushed() {
+    let mut builder = App::build();
+    builder
+        .add_plugin(WebSocketServer::default())
+        .add_system(greet_clients.system());
+    let url = listen(&mut builder.world_mut().get_resource_mut::<Server>().unwrap());
+    let mut app = builder.app;
+
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut ws, _) = tungstenite::connect(url).unwrap();
+        let connected = Instant::now();
+        let received = read_greetings(&mut ws, 3);
+        done_tx.send((connected, received)).unwrap();
+    });
+
+    let (connected, received) = update_until(&mut app, &done_rx);
+    assert_eq!(received.iter().map(|(n, _)| *n).collect::<Vec<_>>(), vec![0, 1, 2]);
+    let (_, last) = received.last().unwrap();
+    assert!(
+        last.duration_since(connected) < FLUSH_DEADLINE,
+        "messages took {:?} to arrive",
+        last.duration_since(connected)
+    );
+}
+
+#[test]
+fn client_sends_from_systems_are_flushed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        // the client expects its codec to be acknowledged, the signature is imposed by
+        // tungstenite
+        #[allow(clippy::result_large_err)]
+        let acknowledge = |_: &Request, mut response: Response| {
+            response
+                .headers_mut()
+                .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static("bwa.json.v1"));
+            Ok(response)
+        };
+        let mut ws = tungstenite::accept_hdr(stream, acknowledge).unwrap();
+        let connected = Instant::now();
+        let received = read_greetings(&mut ws, 3);
+        done_tx.send((connected, received)).unwrap();
+    });
+
+    let mut builder = App::build();
+    builder
+        .add_plugin(WebSocketClient::default())
+        .add_system(greet_server.system());
+    builder
+        .world_mut()
+        .get_resource_mut::<Client>()
+        .unwrap()
+        .connect(url)
+        .unwrap();
+    let mut app = builder.app;
+
+    let (connected, received) = update_until(&mut app, &done_rx);
+    assert_eq!(received.iter().map(|(n, _)| *n).collect::<Vec<_>>(), vec![0, 1, 2]);
+    let (_, last) = received.last().unwrap();
+    assert!(
+        last.duration_since(connected) < FLUSH_DEADLINE,
+        "messages took {:?} to arrive",
+        last.duration_since(connected)
+    );
+}
@@ -0,0 +1,197 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    server::{Identity, Rejection, Request, Server, ServerConfig, StatusCode},
+    shared::{
+        CloseCode, Codec, DisconnectReason, JsonCodec, MessageLimits, NetworkError, NetworkEvent,
+    },
+};
+use common::{listen, wait_for};
+use std::{io::Write, net::TcpStream};
+use tungstenite::{
+    client::IntoClientRequest,
+    http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue},
+    Message,
+};
+
+/// JSON under another name, to tell which codec a connection negotiated.
+struct OtherJson;
+
+impl Codec for OtherJson {
+    fn name(&self) -> &'static str {
+        "other"
+    }
+
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> anyhow::Result<Vec<u8>> {
+        JsonCodec.encode(value)
+    }
+
+    fn decode(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(&mut dyn erased_serde::Deserializer) -> Result<(), erased_serde::Error>,
+    ) -> anyhow::Result<()> {
+        JsonCodec.decode(bytes, visit)
+    }
+}
+
+#[test]
+fn subprotocol_selects_the_codec() {
+    let mut server = Server::new();
+    server.add_codec(OtherJson);
+    let url = listen(&mut server);
+
+    let mut peers = Vec::new();
+    // the first codec is used when nothing was agreed on
+    for (offered, expected, codec) in [
+        (Some("bwa.unknown.v1, bwa.other.v1"), Some("bwa.other.v1"), "other"),
+        (Some("bwa.json.v1, bwa.other.v1"), Some("bwa.json.v1"), "json"),
+        (Some("bwa.unknown.v1"), None, "json"),
+        (None, None, "json"),
+    ] {
+        let mut request = url.as_str().into_client_request().unwrap();
+        if let Some(offered) = offered {
+            request
+                .headers_mut()
+                .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(offered));
+        }
+        let (ws, response) = tungstenite::connect(request).unwrap();
+        peers.push(ws);
+        let accepted = response
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .map(|v| v.to_str().unwrap());
+        assert_eq!(accepted, expected, "offered {:?}", offered);
+
+        let (handle, info) = match wait_for(|| server.recv()) {
+            NetworkEvent::Connected(handle, info) => (handle, info),
+            other => panic!("expected a connection, got {:?}", other),
+        };
+        assert_eq!(info.subprotocol.as_deref(), expected);
+        assert_eq!(server.codec_for(&handle).name(), codec, "offered {:?}", offered);
+    }
+}
+
+#[test]
+fn oversized_messages_close_the_connection() {
+    let limits = MessageLimits {
+        max_message_size: Some(1024),
+        ..Default::default()
+    };
+    let mut server = Server::new();
+    server.set_limits(limits);
+    let url = listen(&mut server);
+
+    let (mut ws, _) = tungstenite::connect(url).unwrap();
+    let handle = match wait_for(|| server.recv()) {
+        NetworkEvent::Connected(handle, _) => handle,
+        other => panic!("expected a connection, got {:?}", other),
+    };
+    ws.write_message(Message::Binary(vec![0; 4096])).unwrap();
+
+    loop {
+        match ws.read_message().expect("failed to read message") {
+            Message::Close(frame) => {
+                assert_eq!(frame.unwrap().code, CloseCode::Size);
+                break;
+            }
+            Message::Ping(_) | Message::Pong(_) => {}
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Error(Some(h), NetworkError::MessageTooLarge { size, max_size }) => {
+            assert_eq!(h, handle);
+            assert!(size > max_size);
+            assert_eq!(max_size, 1024);
+        }
+        other => panic!("expected a size error, got {:?}", other),
+    }
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Disconnected(h, reason) => {
+            assert_eq!(h, handle);
+            assert_eq!(reason, DisconnectReason::MessageTooLarge);
+        }
+        other => panic!("expected a disconnection, got {:?}", other),
+    }
+}
+
+#[test]
+fn failed_upgrades_carry_no_handle() {
+    let mut server = Server::new();
+    listen(&mut server);
+
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Error(None, NetworkError::Handshake(_)) => {}
+        other => panic!("expected a failed handshake, got {:?}", other),
+    }
+    assert!(server.rtts().is_empty());
+}
+
+#[test]
+fn rejected_upgrades_never_connect() {
+    let authenticate = |request: &Request| match request.uri().query() {
+        Some("token=secret") => Ok(Identity::new("alice")),
+        _ => Err(Rejection::unauthorized()),
+    };
+    let mut server = Server::with_config(ServerConfig::default().authenticate(authenticate));
+    let url = listen(&mut server);
+
+    match tungstenite::connect(format!("{}/?token=wrong", url)) {
+        Err(tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED)
+        }
+        other => panic!("expected the upgrade to be refused, got {:?}", other.map(|_| ())),
+    }
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Error(None, NetworkError::Rejected(status)) => {
+            assert_eq!(status, StatusCode::UNAUTHORIZED)
+        }
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+
+    // the next event belongs to the accepted connection
+    let (_ws, _) = tungstenite::connect(format!("{}/?token=secret", url)).unwrap();
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Connected(_, info) => {
+            assert_eq!(info.identity.as_ref().unwrap().id, "alice")
+        }
+        other => panic!("expected a connection, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn unacknowledged_codec_fails_the_connection() {
+    use bevy_websocket_adapter::client::Client;
+
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    client.set_codec(OtherJson);
+    client.connect(url).unwrap();
+
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Error(_, NetworkError::CodecMismatch { expected, acknowledged }) => {
+            assert_eq!(expected, "bwa.other.v1");
+            assert_eq!(acknowledged, None);
+        }
+        other => panic!("expected a codec mismatch, got {:?}", other),
+    }
+    assert!(client.connection_info().is_none());
+
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Connected(..) => {}
+        other => panic!("expected a connection, got {:?}", other),
+    }
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Disconnected(_, DisconnectReason::Closed { code, .. }) => {
+            assert_eq!(code, CloseCode::Protocol)
+        }
+        other => panic!("expected the client to close the connection, got {:?}", other),
+    }
+}
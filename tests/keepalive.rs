@@ -0,0 +1,63 @@
+#![cfg(all(feature = "server", feature = "client"))]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    client::Client,
+    server::Server,
+    shared::{DisconnectReason, KeepaliveConfig, NetworkEvent},
+};
+use common::{connect, listen, wait_for};
+use std::time::Duration;
+
+fn keepalive() -> KeepaliveConfig {
+    KeepaliveConfig {
+        ping_interval: Duration::from_millis(20),
+        pong_timeout: Duration::from_millis(100),
+        idle_timeout: None,
+    }
+}
+
+#[test]
+fn silent_peer_times_out() {
+    let mut server = Server::new();
+    server.set_keepalive(Some(keepalive()));
+    let url = listen(&mut server);
+
+    // never read, so the pings are never answered
+    let (_ws, _) = tungstenite::connect(url).unwrap();
+    let handle = match wait_for(|| server.recv()) {
+        NetworkEvent::Connected(handle, _) => handle,
+        other => panic!("expected a connection, got {:?}", other),
+    };
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Disconnected(h, reason) => {
+            assert_eq!(h, handle);
+            assert_eq!(reason, DisconnectReason::Timeout);
+        }
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn round_trip_times_are_measured_on_both_ends() {
+    let mut server = Server::new();
+    server.set_keepalive(Some(keepalive()));
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    client.set_keepalive(Some(keepalive()));
+    let handle = connect(&server, &mut client, &url);
+
+    let stats = wait_for(|| server.rtt(&handle));
+    assert!(stats.samples >= 1);
+    assert!(stats.rtt < Duration::from_secs(1));
+    assert!(server.rtts().iter().any(|(h, _)| *h == handle));
+
+    let stats = wait_for(|| client.rtt());
+    assert!(stats.samples >= 1);
+    assert!(stats.rtt < Duration::from_secs(1));
+    // nothing was dropped while measuring
+    if let Some(ev) = client.try_recv() {
+        panic!("unexpected event {:?}", ev);
+    }
+}
@@ -0,0 +1,111 @@
+#![cfg(all(feature = "bevy-plugin", feature = "server"))]
+
+mod common;
+
+use ::bevy::{app::Events, prelude::*};
+use bevy_websocket_adapter::{
+    bevy::{Outgoing, WebSocketServer, WsMessageInserter},
+    server::Server,
+    shared::{ConnectionHandle, NetworkEvent},
+};
+use common::{listen, wait_for, Greeting};
+use std::{net::TcpStream, time::Duration};
+use tungstenite::{Message, WebSocket};
+
+/// Handles of the connections, in the order they connected.
+#[derive(Default)]
+struct Connections(Vec<ConnectionHandle>);
+
+fn track_connections(mut evs: EventReader<NetworkEvent>, mut connections: ResMut<Connections>) {
+    for ev in evs.iter() {
+        if let NetworkEvent::Connected(handle, _) = ev {
+            connections.0.push(handle.clone());
+        }
+    }
+}
+
+/// Run the app until `done` returns true.
+fn update_until(app: &mut App, mut done: impl FnMut(&mut App) -> bool) {
+    wait_for(|| {
+        if done(app) {
+            return Some(());
+        }
+        app.update();
+        None
+    })
+}
+
+/// Read greetings until the one numbered `last`, returning all their numbers.
+fn read_until(ws: &mut WebSocket<TcpStream>, last: u32) -> Vec<u32> {
+    let mut received = Vec::new();
+    loop {
+        match ws.read_message().expect("failed to read message") {
+            Message::Binary(bytes) => {
+                let v: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+                assert_eq!(v["t"], "greeting");
+                let n = v["d"]["n"].as_u64().unwrap() as u32;
+                received.push(n);
+                if n == last {
+                    return received;
+                }
+            }
+            Message::Ping(_) | Message::Pong(_) => {}
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn outgoing_messages_reach_their_recipients() {
+    let mut builder = App::build();
+    builder
+        .add_plugin(WebSocketServer::default())
+        .add_outgoing_type::<Greeting>()
+        .init_resource::<Connections>()
+        .add_system(track_connections.system());
+    let url = listen(&mut builder.world_mut().get_resource_mut::<Server>().unwrap());
+    let mut app = builder.app;
+    let addr = app.world.get_resource::<Server>().unwrap().local_addr().unwrap();
+
+    let mut peers = Vec::new();
+    for i in 0..3 {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let (ws, _) = tungstenite::client(url.as_str(), stream).unwrap();
+        peers.push(ws);
+        update_until(&mut app, |app| {
+            app.world.get_resource::<Connections>().unwrap().0.len() > i
+        });
+    }
+    let handles = app.world.get_resource::<Connections>().unwrap().0.clone();
+    let (a, b, c) = (handles[0].clone(), handles[1].clone(), handles[2].clone());
+
+    let server = app.world.get_resource::<Server>().unwrap();
+    server.join(&a, "red").unwrap();
+    server.join(&c, "red").unwrap();
+
+    let mut outgoing = app
+        .world
+        .get_resource_mut::<Events<Outgoing<Greeting>>>()
+        .unwrap();
+    outgoing.send(Outgoing::to(a.clone(), Greeting { n: 1 }));
+    outgoing.send(Outgoing::to_many(vec![b.clone(), c], Greeting { n: 2 }));
+    outgoing.send(Outgoing::to_room("red", Greeting { n: 3 }));
+    outgoing.send(Outgoing::to_all_except(b, Greeting { n: 4 }));
+    outgoing.send(Outgoing::to_all(Greeting { n: 5 }));
+    app.update();
+
+    let received: Vec<_> = peers.iter_mut().map(|ws| read_until(ws, 5)).collect();
+    assert_eq!(received[0], vec![1, 3, 4, 5]);
+    assert_eq!(received[1], vec![2, 5]);
+    assert_eq!(received[2], vec![2, 3, 4, 5]);
+}
+
+#[test]
+#[cfg(feature = "client")]
+#[should_panic(expected = "before WebSocketServer initialization")]
+fn outgoing_type_requires_the_server_plugin() {
+    App::build()
+        .add_plugin(bevy_websocket_adapter::bevy::WebSocketClient::default())
+        .add_outgoing_type::<Greeting>();
+}
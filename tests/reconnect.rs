@@ -0,0 +1,77 @@
+#![cfg(all(feature = "server", feature = "client"))]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    client::{Client, ReconnectPolicy},
+    server::Server,
+    shared::{CloseCode, DisconnectReason, NetworkEvent},
+};
+use common::{connect, listen, wait_for};
+use std::{thread, time::Duration};
+
+fn policy(reconnect_after_close: bool) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_delay: Duration::from_millis(10),
+        jitter: 0.0,
+        reconnect_after_close,
+        ..Default::default()
+    }
+}
+
+/// Connect `client` to `url`, and kick it once both ends saw the connection.
+fn kick(server: &Server, client: &mut Client, url: &str) {
+    let handle = connect(server, client, url);
+    server.disconnect(&handle, CloseCode::Policy, "banned").unwrap();
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Disconnected(_, reason) => assert_eq!(
+            reason,
+            DisconnectReason::Closed {
+                code: CloseCode::Policy,
+                reason: "banned".to_string()
+            }
+        ),
+        other => panic!("expected a disconnection, got {:?}", other),
+    }
+}
+
+#[test]
+fn kicked_client_stays_disconnected() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    client.set_reconnect_policy(Some(policy(false)));
+
+    kick(&server, &mut client, &url);
+
+    thread::sleep(Duration::from_millis(300));
+    if let Some(ev) = client.try_recv() {
+        panic!("expected the client to stay disconnected, got {:?}", ev);
+    }
+    while let Some(ev) = server.recv() {
+        assert!(
+            matches!(ev, NetworkEvent::Disconnected(..)),
+            "expected no new connection, got {:?}",
+            ev
+        );
+    }
+}
+
+#[test]
+fn kicked_client_reconnects_if_asked_to() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let mut client = Client::new();
+    client.set_reconnect_policy(Some(policy(true)));
+
+    kick(&server, &mut client, &url);
+
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Reconnecting(_, 1) => {}
+        other => panic!("expected the client to reconnect, got {:?}", other),
+    }
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Connected(..) => {}
+        other => panic!("expected the client to connect again, got {:?}", other),
+    }
+}
@@ -0,0 +1,121 @@
+#![cfg(all(feature = "server", feature = "client"))]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    client::{Client, ClientConfig, PendingResponse},
+    impl_message_type,
+    server::Server,
+    shared::{Enveloppe, ErrorMessage, NetworkError, NetworkEvent},
+};
+use serde::{Deserialize, Serialize};
+use common::{connect, listen, wait_for, Greeting};
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Add {
+    a: i32,
+    b: i32,
+}
+impl_message_type!(Add, "add");
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Sum {
+    n: i32,
+}
+impl_message_type!(Sum, "sum");
+
+/// Answer the pending requests: negative operands are an error, and zero is never answered.
+fn serve(server: &Server) {
+    while let Some(ev) = server.recv() {
+        if let NetworkEvent::Message(handle, bytes) = ev {
+            let enveloppe = Enveloppe::decode(server.codec_for(&handle), bytes).unwrap();
+            let id = enveloppe.correlation_id.expect("requests have a correlation id");
+            let Add { a, b } = enveloppe.payload().unwrap();
+            if a < 0 || b < 0 {
+                let error = ErrorMessage::new("negative", "operands must be positive");
+                server.send_error(&handle, Some(id), &error).unwrap();
+            } else if a != 0 && b != 0 {
+                server.respond(&handle, id, &Sum { n: a + b }).unwrap();
+            }
+        }
+    }
+}
+
+/// Serve requests until `poll` yields a value, for at most 10 seconds.
+fn serve_until<T>(server: &Server, mut poll: impl FnMut() -> Option<T>) -> T {
+    wait_for(|| {
+        serve(server);
+        poll()
+    })
+}
+
+fn request(client: &Client, server: &Server, a: i32, b: i32) -> Result<Sum, NetworkError> {
+    let mut pending: PendingResponse<Sum> = client.request(&Add { a, b }).unwrap();
+    serve_until(server, || pending.try_recv())
+}
+
+#[test]
+fn requests_resolve_with_a_response_an_error_or_a_timeout() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+
+    let config = ClientConfig::default().request_timeout(Duration::from_millis(200));
+    let mut client = Client::with_config(config);
+    client.connect(url).unwrap();
+    serve_until(&server, || match client.try_recv() {
+        Some(NetworkEvent::Connected(..)) => Some(()),
+        Some(other) => panic!("expected the client to connect, got {:?}", other),
+        None => None,
+    });
+
+    assert_eq!(request(&client, &server, 1, 2).unwrap(), Sum { n: 3 });
+
+    match request(&client, &server, -1, 2) {
+        Err(NetworkError::Remote(error)) => assert_eq!(error.code, "negative"),
+        other => panic!("expected a remote error, got {:?}", other),
+    }
+
+    let start = Instant::now();
+    match request(&client, &server, 0, 2) {
+        Err(NetworkError::RequestTimeout) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // answers never reach the client's events
+    assert!(client.try_recv().is_none());
+}
+
+#[test]
+fn late_responses_are_dropped() {
+    let mut server = Server::new();
+    let url = listen(&mut server);
+    let config = ClientConfig::default().request_timeout(Duration::from_millis(100));
+    let mut client = Client::with_config(config);
+    let handle = connect(&server, &mut client, &url);
+
+    let mut pending: PendingResponse<Sum> = client.request(&Add { a: 1, b: 2 }).unwrap();
+    let id = match wait_for(|| server.recv()) {
+        NetworkEvent::Message(_, bytes) => {
+            let enveloppe = Enveloppe::decode(server.codec_for(&handle), bytes).unwrap();
+            enveloppe.correlation_id.unwrap()
+        }
+        other => panic!("expected a request, got {:?}", other),
+    };
+    match wait_for(|| pending.try_recv()) {
+        Err(NetworkError::RequestTimeout) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+
+    server.respond(&handle, id, &Sum { n: 3 }).unwrap();
+    server.respond(&handle, id + 100, &Sum { n: 4 }).unwrap();
+    server.send_message(&handle, &Greeting { n: 1 }).unwrap();
+    match wait_for(|| client.try_recv()) {
+        NetworkEvent::Message(_, bytes) => {
+            let enveloppe = Enveloppe::decode(server.codec_for(&handle), bytes).unwrap();
+            assert_eq!(enveloppe.payload::<Greeting>().unwrap(), Greeting { n: 1 });
+        }
+        other => panic!("expected the greeting, got {:?}", other),
+    }
+}
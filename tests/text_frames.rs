@@ -0,0 +1,44 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use bevy_websocket_adapter::{
+    server::Server,
+    shared::{Enveloppe, FrameKind, NetworkEvent},
+};
+use common::{listen, wait_for, Greeting};
+use tungstenite::Message;
+
+#[test]
+fn text_frames_are_decoded_and_sent() {
+    let mut server = Server::new();
+    server.set_frame_kind(FrameKind::Text);
+    let url = listen(&mut server);
+
+    let (mut ws, _) = tungstenite::connect(url).unwrap();
+    let handle = match wait_for(|| server.recv()) {
+        NetworkEvent::Connected(handle, _) => handle,
+        other => panic!("expected a connection, got {:?}", other),
+    };
+    ws.write_message(Message::Text(r#"{"t":"greeting","d":{"n":1}}"#.to_string()))
+        .unwrap();
+    match wait_for(|| server.recv()) {
+        NetworkEvent::Message(from, bytes) => {
+            assert_eq!(from, handle);
+            let enveloppe = Enveloppe::decode(server.codec_for(&handle), bytes).unwrap();
+            assert_eq!(enveloppe.message_type, "greeting");
+            assert_eq!(enveloppe.payload::<Greeting>().unwrap(), Greeting { n: 1 });
+        }
+        other => panic!("expected a message, got {:?}", other),
+    }
+
+    server.send_message(&handle, &Greeting { n: 2 }).unwrap();
+    match ws.read_message().unwrap() {
+        Message::Text(text) => {
+            let v: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(v["t"], "greeting");
+            assert_eq!(v["d"]["n"], 2);
+        }
+        other => panic!("expected a text frame, got {:?}", other),
+    }
+}